@@ -0,0 +1,18 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+#[allow(dead_code)]
+#[path = "../../src/model.rs"]
+mod model;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(body) = std::str::from_utf8(data) else {
+        return;
+    };
+
+    // Whatever upstream sends has to come back as a feed, a reported error or a parse error.
+    match model::parse_envelope::<model::PollutionData>(body) {
+        Ok(model::Envelope::Ok(_)) | Ok(model::Envelope::Error(_)) | Err(_) => {}
+    }
+});
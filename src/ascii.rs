@@ -0,0 +1,132 @@
+use crate::{store::UserSettings, Bot};
+use std::ops::Deref;
+use teloxide::{
+    prelude::*,
+    types::{MessageId, Recipient},
+};
+
+/// Emoji and symbols of the reports and what they become in ASCII mode. Longer sequences come
+/// first so `❤️` is replaced before its bare `❤`.
+const REPLACEMENTS: [(&str, &str); 22] = [
+    ("🌳 [", "|"),
+    ("] 💀", "|"),
+    ("💚", "[GOOD]"),
+    ("💛", "[MODERATE]"),
+    ("🧡", "[USG]"),
+    ("❤️", "[UNHEALTHY]"),
+    ("❤", "[UNHEALTHY]"),
+    ("💜", "[VERY UNHEALTHY]"),
+    ("🖤", "[HAZARDOUS]"),
+    ("🔸", "+"),
+    ("🌳", "|"),
+    ("💀", "|"),
+    ("█", "#"),
+    ("░", "."),
+    ("➔", " ->"),
+    ("⚠️", "!"),
+    ("ℹ️", "i"),
+    ("🩺", "*"),
+    ("🚬", "*"),
+    ("≈", "~"),
+    ("×", "x"),
+    ("µg/m³", "ug/m3"),
+];
+
+/// `text` with the emoji of a report swapped for ASCII, for clients that can't show emoji.
+/// Emoji without an ASCII equivalent are dropped; other text, like city names in their own
+/// script, is kept as is.
+pub fn asciify(text: &str) -> String {
+    let mut text = text.to_owned();
+    for (emoji, ascii) in REPLACEMENTS {
+        text = text.replace(emoji, ascii);
+    }
+    // A dropped emoji takes the space that separated it from the text along.
+    let mut ascii = String::with_capacity(text.len());
+    let mut dropped = false;
+    for c in text.chars() {
+        if is_emoji(c) {
+            dropped = true;
+            continue;
+        }
+        if !(dropped && c == ' ') {
+            ascii.push(c);
+        }
+        dropped = false;
+    }
+    ascii
+}
+
+/// Whether `c` is a pictograph or one of the invisible characters emoji are built with.
+fn is_emoji(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x1F000..=0x1FAFF | 0x2600..=0x27BF | 0x2B00..=0x2BFF | 0xFE0F | 0x200D
+    )
+}
+
+/// The bot as one chat sees it: text sent or edited through it is written in ASCII if the chat
+/// turned ASCII mode on, so no reply has to remember to convert itself. Everything else derefs
+/// to the `Bot`.
+#[derive(Clone)]
+pub struct ChatBot {
+    bot: Bot,
+    ascii: bool,
+}
+
+impl ChatBot {
+    pub fn new(bot: Bot, settings: &UserSettings) -> Self {
+        Self {
+            bot,
+            ascii: settings.ascii,
+        }
+    }
+
+    /// `text` as the chat wants to read it.
+    pub fn text(&self, text: impl Into<String>) -> String {
+        let text = text.into();
+        if self.ascii {
+            asciify(&text)
+        } else {
+            text
+        }
+    }
+
+    pub fn send_message(
+        &self,
+        chat_id: impl Into<Recipient>,
+        text: impl Into<String>,
+    ) -> <Bot as Requester>::SendMessage {
+        self.bot.send_message(chat_id, self.text(text))
+    }
+
+    pub fn edit_message_text(
+        &self,
+        chat_id: impl Into<Recipient>,
+        message_id: MessageId,
+        text: impl Into<String>,
+    ) -> <Bot as Requester>::EditMessageText {
+        self.bot
+            .edit_message_text(chat_id, message_id, self.text(text))
+    }
+}
+
+impl Deref for ChatBot {
+    type Target = Bot;
+
+    fn deref(&self) -> &Bot {
+        &self.bot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn emoji_are_replaced_or_dropped() {
+        assert_eq!(asciify("💚🔸 Good"), "[GOOD]+ Good");
+        assert_eq!(asciify("❤️ Unhealthy"), "[UNHEALTHY] Unhealthy");
+        assert_eq!(asciify("🌳 [███░░] 💀"), "|###..|");
+        assert_eq!(asciify("🗓️ Digest for تهران"), "Digest for تهران");
+    }
+}
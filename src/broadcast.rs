@@ -0,0 +1,46 @@
+use crate::Bot;
+use std::time::Duration;
+use teloxide::{prelude::*, RequestError};
+
+/// Gap between two messages. The bot's throttle would pace them anyway, this leaves room in
+/// its budget for replies to commands while a broadcast runs.
+const SEND_INTERVAL: Duration = Duration::from_millis(40);
+
+/// Times a single chat is retried after Telegram asks to slow down.
+const MAX_RETRIES: usize = 3;
+
+/// Sends `text` to every chat in the background and reports the outcome to `reporter`, since a
+/// broadcast easily outlasts the command timeout.
+pub fn spawn(bot: Bot, reporter: ChatId, chats: Vec<ChatId>, text: String) {
+    tokio::spawn(async move {
+        let (mut delivered, mut failed) = (0, 0);
+        for chat_id in chats {
+            if send(&bot, chat_id, &text).await {
+                delivered += 1;
+            } else {
+                failed += 1;
+            }
+            tokio::time::sleep(SEND_INTERVAL).await;
+        }
+
+        let report = format!("Broadcast finished: {delivered} delivered, {failed} failed.");
+        if let Err(e) = bot.send_message(reporter, report).await {
+            tracing::warn!("Failed to report broadcast to {reporter}: {e}");
+        }
+    });
+}
+
+async fn send(bot: &Bot, chat_id: ChatId, text: &str) -> bool {
+    for _ in 0..=MAX_RETRIES {
+        match bot.send_message(chat_id, text).await {
+            Ok(_) => return true,
+            Err(RequestError::RetryAfter(wait)) => tokio::time::sleep(wait.duration()).await,
+            Err(e) => {
+                tracing::warn!("Failed to broadcast to {chat_id}: {e}");
+                return false;
+            }
+        }
+    }
+    tracing::warn!("Gave up broadcasting to {chat_id} after {MAX_RETRIES} retries");
+    false
+}
@@ -0,0 +1,78 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// A string-keyed map whose entries expire `ttl` after they were inserted.
+pub struct TtlCache<V> {
+    /// In milliseconds, so `/reload` can change it while the cache is shared.
+    ttl_ms: AtomicU64,
+    entries: Mutex<HashMap<String, (Instant, V)>>,
+}
+
+impl<V: Clone> TtlCache<V> {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl_ms: AtomicU64::new(ttl.as_millis() as u64),
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn ttl(&self) -> Duration {
+        Duration::from_millis(self.ttl_ms.load(Ordering::Relaxed))
+    }
+
+    /// Changes the TTL of every entry, including the ones already cached.
+    pub fn set_ttl(&self, ttl: Duration) {
+        self.ttl_ms.store(ttl.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    pub fn get(&self, key: &str) -> Option<V> {
+        let ttl = self.ttl();
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(key) {
+            Some((inserted, value)) if inserted.elapsed() < ttl => Some(value.clone()),
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Every live entry with how long ago it was inserted.
+    pub fn entries(&self) -> Vec<(String, Duration, V)> {
+        let ttl = self.ttl();
+        let entries = self.entries.lock().unwrap();
+        entries
+            .iter()
+            .filter(|(_, (inserted, _))| inserted.elapsed() < ttl)
+            .map(|(key, (inserted, value))| (key.clone(), inserted.elapsed(), value.clone()))
+            .collect()
+    }
+
+    /// Adds an entry that was inserted `age` ago, unless it has expired since.
+    pub fn insert_aged(&self, key: String, age: Duration, value: V) {
+        if age >= self.ttl() {
+            return;
+        }
+        let Some(inserted) = Instant::now().checked_sub(age) else {
+            return;
+        };
+        self.entries.lock().unwrap().insert(key, (inserted, value));
+    }
+
+    pub fn insert(&self, key: String, value: V) {
+        let ttl = self.ttl();
+        if ttl.is_zero() {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, (inserted, _)| inserted.elapsed() < ttl);
+        entries.insert(key, (Instant::now(), value));
+    }
+}
@@ -0,0 +1,83 @@
+//! The European Common Air Quality Index (CAQI), hourly background grid.
+
+use std::fmt;
+
+/// The five CAQI bands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EuLevel {
+    VeryLow,
+    Low,
+    Medium,
+    High,
+    VeryHigh,
+}
+
+impl EuLevel {
+    /// Position on the scale, 0 for the cleanest band.
+    pub fn rank(self) -> usize {
+        self as usize
+    }
+}
+
+impl fmt::Display for EuLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EuLevel::VeryLow => f.write_str("Very low"),
+            EuLevel::Low => f.write_str("Low"),
+            EuLevel::Medium => f.write_str("Medium"),
+            EuLevel::High => f.write_str("High"),
+            EuLevel::VeryHigh => f.write_str("Very high"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Caqi {
+    pub index: u32,
+    pub level: EuLevel,
+}
+
+/// Upper concentration (µg/m³) of the first four bands; each band spans 25 index points.
+fn grid(pollutant: &str) -> Option<[f64; 4]> {
+    match pollutant {
+        "pm25" => Some([15.0, 30.0, 55.0, 110.0]),
+        "pm10" => Some([25.0, 50.0, 90.0, 180.0]),
+        "o3" => Some([60.0, 120.0, 180.0, 240.0]),
+        "no2" => Some([50.0, 100.0, 200.0, 400.0]),
+        _ => None,
+    }
+}
+
+/// CAQI for a concentration in µg/m³, or `None` if the pollutant isn't part of the index.
+pub fn caqi(pollutant: &str, concentration: f64) -> Option<Caqi> {
+    let grid = grid(pollutant)?;
+    if !concentration.is_finite() || concentration < 0.0 {
+        return None;
+    }
+
+    let levels = [
+        EuLevel::VeryLow,
+        EuLevel::Low,
+        EuLevel::Medium,
+        EuLevel::High,
+    ];
+    let mut low = 0.0;
+    for (i, (high, level)) in grid.iter().zip(levels).enumerate() {
+        if concentration <= *high {
+            let index = 25.0 * i as f64 + (concentration - low) / (high - low) * 25.0;
+            return Some(Caqi {
+                index: index.round() as u32,
+                level,
+            });
+        }
+        low = *high;
+    }
+
+    // Above the grid the index keeps growing at the rate of the last band.
+    let last_span = grid[3] - grid[2];
+    let index = 100.0 + (concentration - grid[3]) / last_span * 25.0;
+    Some(Caqi {
+        index: index.round() as u32,
+        level: EuLevel::VeryHigh,
+    })
+}
@@ -0,0 +1,261 @@
+use aqi::{AirQuality, AirQualityLevel};
+use plotters::{
+    prelude::*,
+    style::text_anchor::{HPos, Pos, VPos},
+};
+use std::sync::Once;
+
+const FONT: &[u8] = include_bytes!("../assets/fonts/DejaVuSans.ttf");
+const FONT_FAMILY: &str = "sans-serif";
+
+const CELL_WIDTH: u32 = 110;
+const CELL_HEIGHT: u32 = 90;
+const MARGIN: u32 = 20;
+const TITLE_HEIGHT: u32 = 40;
+const LABEL_HEIGHT: u32 = 30;
+
+/// Official US EPA color for each band.
+pub fn band_color(level: AirQualityLevel) -> RGBColor {
+    use AirQualityLevel::*;
+
+    match level {
+        Good => RGBColor(0, 228, 0),
+        Moderate => RGBColor(255, 255, 0),
+        UnhealthySensitive => RGBColor(255, 126, 0),
+        Unhealthy => RGBColor(255, 0, 0),
+        VeryUnhealthy => RGBColor(143, 63, 151),
+        Hazardous => RGBColor(126, 0, 35),
+    }
+}
+
+/// Text color that stays readable on top of `band_color(level)`.
+fn text_color(level: AirQualityLevel) -> RGBColor {
+    use AirQualityLevel::*;
+
+    match level {
+        Good | Moderate | UnhealthySensitive => BLACK,
+        Unhealthy | VeryUnhealthy | Hazardous => WHITE,
+    }
+}
+
+fn register_font() {
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(|| {
+        if plotters::style::register_font(FONT_FAMILY, FontStyle::Normal, FONT).is_err() {
+            tracing::error!("Failed to register the bundled chart font");
+        }
+    });
+}
+
+/// Renders one colored cell per forecast day, labeled with the day beneath, as a PNG.
+pub fn forecast_heatmap(title: &str, days: &[(String, AirQuality)]) -> Result<Vec<u8>, String> {
+    if days.is_empty() {
+        return Err("Nothing to render".into());
+    }
+    register_font();
+
+    let width = MARGIN * 2 + CELL_WIDTH * days.len() as u32;
+    let height = MARGIN * 2 + TITLE_HEIGHT + CELL_HEIGHT + LABEL_HEIGHT;
+    let mut buffer = vec![0u8; (width * height * 3) as usize];
+
+    {
+        let root = BitMapBackend::with_buffer(&mut buffer, (width, height)).into_drawing_area();
+        root.fill(&WHITE).map_err(|e| e.to_string())?;
+
+        let center = Pos::new(HPos::Center, VPos::Center);
+        let title_style = TextStyle::from((FONT_FAMILY, 22).into_font()).pos(center);
+        root.draw(&Text::new(
+            title.to_owned(),
+            ((width / 2) as i32, (MARGIN + TITLE_HEIGHT / 2) as i32),
+            title_style,
+        ))
+        .map_err(|e| e.to_string())?;
+
+        let top = (MARGIN + TITLE_HEIGHT) as i32;
+        for (i, (day, aqi_level)) in days.iter().enumerate() {
+            let left = (MARGIN + CELL_WIDTH * i as u32) as i32;
+            let right = left + CELL_WIDTH as i32;
+            let bottom = top + CELL_HEIGHT as i32;
+
+            root.draw(&Rectangle::new(
+                [(left, top), (right, bottom)],
+                band_color(aqi_level.level()).filled(),
+            ))
+            .map_err(|e| e.to_string())?;
+            root.draw(&Rectangle::new(
+                [(left, top), (right, bottom)],
+                WHITE.stroke_width(2),
+            ))
+            .map_err(|e| e.to_string())?;
+
+            let value_color = text_color(aqi_level.level());
+            let value_style = TextStyle::from((FONT_FAMILY, 28).into_font())
+                .color(&value_color)
+                .pos(center);
+            root.draw(&Text::new(
+                aqi_level.aqi().to_string(),
+                ((left + right) / 2, (top + bottom) / 2),
+                value_style,
+            ))
+            .map_err(|e| e.to_string())?;
+
+            let label_style = TextStyle::from((FONT_FAMILY, 16).into_font()).pos(center);
+            root.draw(&Text::new(
+                day.clone(),
+                ((left + right) / 2, bottom + LABEL_HEIGHT as i32 / 2),
+                label_style,
+            ))
+            .map_err(|e| e.to_string())?;
+        }
+
+        root.present().map_err(|e| e.to_string())?;
+    }
+
+    encode_png(&buffer, width, height)
+}
+
+/// Size of a reading card, picked to preview well in chats and on social media.
+const CARD_WIDTH: u32 = 600;
+const CARD_HEIGHT: u32 = 340;
+const CARD_TITLE_LINE_HEIGHT: u32 = 34;
+/// City names longer than this many lines are cut short.
+const CARD_TITLE_LINES: usize = 2;
+/// Characters of the title font that fit on one line of the card.
+const CARD_TITLE_LINE_CHARS: usize = 32;
+const CARD_BAND_HEIGHT: u32 = 170;
+
+/// Renders a single reading as a shareable card: the city, a big AQI on its band's color with
+/// the band's name, when the reading was taken and where it comes from, as a PNG.
+pub fn reading_card(
+    city: &str,
+    aqi_level: AirQuality,
+    level: AirQualityLevel,
+    observed: &str,
+    attribution: &str,
+) -> Result<Vec<u8>, String> {
+    register_font();
+
+    let (width, height) = (CARD_WIDTH, CARD_HEIGHT);
+    let mut buffer = vec![0u8; (width * height * 3) as usize];
+
+    {
+        let root = BitMapBackend::with_buffer(&mut buffer, (width, height)).into_drawing_area();
+        root.fill(&WHITE).map_err(|e| e.to_string())?;
+
+        let left = Pos::new(HPos::Left, VPos::Center);
+        let center = Pos::new(HPos::Center, VPos::Center);
+
+        let title_style = TextStyle::from((FONT_FAMILY, 28).into_font()).pos(left);
+        for (i, line) in wrap_title(city).into_iter().enumerate() {
+            let y = MARGIN + CARD_TITLE_LINE_HEIGHT * i as u32 + CARD_TITLE_LINE_HEIGHT / 2;
+            root.draw(&Text::new(
+                line,
+                (MARGIN as i32, y as i32),
+                title_style.clone(),
+            ))
+            .map_err(|e| e.to_string())?;
+        }
+
+        let top = (MARGIN + CARD_TITLE_LINE_HEIGHT * CARD_TITLE_LINES as u32 + 10) as i32;
+        let bottom = top + CARD_BAND_HEIGHT as i32;
+        root.draw(&Rectangle::new(
+            [(MARGIN as i32, top), ((width - MARGIN) as i32, bottom)],
+            band_color(level).filled(),
+        ))
+        .map_err(|e| e.to_string())?;
+
+        let value_color = text_color(level);
+        let middle = (width / 2) as i32;
+        let value_style = TextStyle::from((FONT_FAMILY, 80).into_font())
+            .color(&value_color)
+            .pos(center);
+        root.draw(&Text::new(
+            aqi_level.aqi().to_string(),
+            (middle, top + 65),
+            value_style,
+        ))
+        .map_err(|e| e.to_string())?;
+        let verdict_style = TextStyle::from((FONT_FAMILY, 26).into_font())
+            .color(&value_color)
+            .pos(center);
+        root.draw(&Text::new(
+            format!("AQI · {level}"),
+            (middle, top + 135),
+            verdict_style,
+        ))
+        .map_err(|e| e.to_string())?;
+
+        let gray = RGBColor(110, 110, 110);
+        let footer_style = TextStyle::from((FONT_FAMILY, 16).into_font())
+            .color(&gray)
+            .pos(left);
+        root.draw(&Text::new(
+            observed.to_owned(),
+            (MARGIN as i32, bottom + 22),
+            footer_style.clone(),
+        ))
+        .map_err(|e| e.to_string())?;
+        root.draw(&Text::new(
+            attribution.to_owned(),
+            (MARGIN as i32, bottom + 46),
+            footer_style,
+        ))
+        .map_err(|e| e.to_string())?;
+
+        root.present().map_err(|e| e.to_string())?;
+    }
+
+    encode_png(&buffer, width, height)
+}
+
+/// `title` broken into at most `CARD_TITLE_LINES` lines on word boundaries, breaking words
+/// that don't fit a line on their own and ending with an ellipsis if it had to be cut.
+fn wrap_title(title: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut current = String::new();
+    for word in title.split_whitespace() {
+        let mut word: Vec<char> = word.chars().collect();
+        loop {
+            let used = current.chars().count();
+            let gap = usize::from(used > 0);
+            if used + gap + word.len() <= CARD_TITLE_LINE_CHARS {
+                if gap == 1 {
+                    current.push(' ');
+                }
+                current.extend(word);
+                break;
+            }
+            if used > 0 {
+                lines.push(std::mem::take(&mut current));
+                continue;
+            }
+            let rest = word.split_off(CARD_TITLE_LINE_CHARS);
+            lines.push(word.into_iter().collect());
+            word = rest;
+        }
+    }
+    if !current.is_empty() {
+        lines.push(current);
+    }
+
+    if lines.len() > CARD_TITLE_LINES {
+        lines.truncate(CARD_TITLE_LINES);
+        let last = &mut lines[CARD_TITLE_LINES - 1];
+        let kept: String = last.chars().take(CARD_TITLE_LINE_CHARS - 1).collect();
+        *last = format!("{}…", kept.trim_end());
+    }
+    lines
+}
+
+fn encode_png(rgb: &[u8], width: u32, height: u32) -> Result<Vec<u8>, String> {
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+    writer.write_image_data(rgb).map_err(|e| e.to_string())?;
+    writer.finish().map_err(|e| e.to_string())?;
+
+    Ok(png)
+}
@@ -0,0 +1,199 @@
+//! WAQI reports the `iaqi` values as US EPA sub-indices rather than concentrations, so
+//! anything that needs µg/m³ has to walk the EPA breakpoints backwards. The token API has no
+//! endpoint with the raw concentrations, so this is the only way to get them.
+
+use std::fmt::Display;
+
+/// One EPA breakpoint: the AQI range and the concentration range it was computed from.
+struct Breakpoint {
+    aqi_low: f64,
+    aqi_high: f64,
+    conc_low: f64,
+    conc_high: f64,
+}
+
+const fn bp(aqi_low: f64, aqi_high: f64, conc_low: f64, conc_high: f64) -> Breakpoint {
+    Breakpoint {
+        aqi_low,
+        aqi_high,
+        conc_low,
+        conc_high,
+    }
+}
+
+/// 24-hour PM2.5 breakpoints in µg/m³.
+const PM25: [Breakpoint; 7] = [
+    bp(0.0, 50.0, 0.0, 12.0),
+    bp(51.0, 100.0, 12.1, 35.4),
+    bp(101.0, 150.0, 35.5, 55.4),
+    bp(151.0, 200.0, 55.5, 150.4),
+    bp(201.0, 300.0, 150.5, 250.4),
+    bp(301.0, 400.0, 250.5, 350.4),
+    bp(401.0, 500.0, 350.5, 500.4),
+];
+
+/// 24-hour PM10 breakpoints in µg/m³.
+const PM10: [Breakpoint; 7] = [
+    bp(0.0, 50.0, 0.0, 54.0),
+    bp(51.0, 100.0, 55.0, 154.0),
+    bp(101.0, 150.0, 155.0, 254.0),
+    bp(151.0, 200.0, 255.0, 354.0),
+    bp(201.0, 300.0, 355.0, 424.0),
+    bp(301.0, 400.0, 425.0, 504.0),
+    bp(401.0, 500.0, 505.0, 604.0),
+];
+
+/// 8-hour ozone breakpoints in ppm (EPA defines no 8-hour values above AQI 300).
+const O3: [Breakpoint; 5] = [
+    bp(0.0, 50.0, 0.0, 0.054),
+    bp(51.0, 100.0, 0.055, 0.070),
+    bp(101.0, 150.0, 0.071, 0.085),
+    bp(151.0, 200.0, 0.086, 0.105),
+    bp(201.0, 300.0, 0.106, 0.200),
+];
+
+/// 1-hour NO2 breakpoints in ppb.
+const NO2: [Breakpoint; 7] = [
+    bp(0.0, 50.0, 0.0, 53.0),
+    bp(51.0, 100.0, 54.0, 100.0),
+    bp(101.0, 150.0, 101.0, 360.0),
+    bp(151.0, 200.0, 361.0, 649.0),
+    bp(201.0, 300.0, 650.0, 1249.0),
+    bp(301.0, 400.0, 1250.0, 1649.0),
+    bp(401.0, 500.0, 1650.0, 2049.0),
+];
+
+/// µg/m³ per ppm of ozone at 25°C.
+const O3_UG_PER_PPM: f64 = 1960.0;
+/// µg/m³ per ppb of NO2 at 25°C.
+const NO2_UG_PER_PPB: f64 = 1.88;
+
+fn from_sub_index(breakpoints: &[Breakpoint], aqi: f64) -> Option<f64> {
+    if !aqi.is_finite() || aqi < 0.0 {
+        return None;
+    }
+    // Sub-indices are integers, but a value between two ranges (e.g. 50.5) belongs to the
+    // upper one.
+    let breakpoint = breakpoints
+        .iter()
+        .find(|b| aqi <= b.aqi_high)
+        .unwrap_or(breakpoints.last()?);
+    let aqi = aqi.max(breakpoint.aqi_low);
+
+    Some(
+        (aqi - breakpoint.aqi_low) / (breakpoint.aqi_high - breakpoint.aqi_low)
+            * (breakpoint.conc_high - breakpoint.conc_low)
+            + breakpoint.conc_low,
+    )
+}
+
+/// PM2.5 concentration in µg/m³ for a WAQI `pm25` sub-index.
+pub fn pm25(aqi: f64) -> Option<f64> {
+    from_sub_index(&PM25, aqi)
+}
+
+/// Concentration in µg/m³ for a WAQI sub-index of `pollutant`, for the pollutants whose
+/// breakpoints are known here.
+pub fn ug_m3(pollutant: &str, aqi: f64) -> Option<f64> {
+    match pollutant {
+        "pm25" => pm25(aqi),
+        "pm10" => from_sub_index(&PM10, aqi),
+        "o3" => from_sub_index(&O3, aqi).map(|ppm| ppm * O3_UG_PER_PPM),
+        "no2" => from_sub_index(&NO2, aqi).map(|ppb| ppb * NO2_UG_PER_PPB),
+        _ => None,
+    }
+}
+
+/// A pollutant value as shown to users, which says whether it's a concentration or the
+/// sub-index WAQI reported, so an index is never passed off as µg/m³.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DisplayValue {
+    /// Worked out from the sub-index, in µg/m³.
+    Concentration(f64),
+    /// WAQI's sub-index as reported, for pollutants whose breakpoints aren't known here.
+    SubIndex(f64),
+}
+
+impl DisplayValue {
+    /// What to show for `pollutant`'s WAQI sub-index: its concentration if it can be worked
+    /// out, the sub-index otherwise.
+    pub fn of(pollutant: &str, sub_index: f64) -> Self {
+        ug_m3(pollutant, sub_index).map_or(Self::SubIndex(sub_index), Self::Concentration)
+    }
+}
+
+impl Display for DisplayValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Concentration(value) => write!(f, "{value:.1} µg/m³"),
+            Self::SubIndex(value) => write!(f, "index {value}"),
+        }
+    }
+}
+
+/// Berkeley Earth's rule of thumb: breathing 22 µg/m³ of PM2.5 for a day is about as harmful
+/// as smoking one cigarette.
+const PM25_PER_CIGARETTE: f64 = 22.0;
+
+/// Cigarettes per day equivalent to a PM2.5 concentration in µg/m³.
+pub fn cigarettes(pm25: f64) -> f64 {
+    pm25 / PM25_PER_CIGARETTE
+}
+
+/// WHO 2021 global air quality guideline levels for 24-hour means, in µg/m³: pollutant key,
+/// display name and limit. Source: WHO global air quality guidelines (2021), table 0.1,
+/// https://www.who.int/publications/i/item/9789240034228
+pub const WHO_24H_GUIDELINES: [(&str, &str, f64); 2] =
+    [("pm25", "PM2.5", 15.0), ("pm10", "PM10", 45.0)];
+
+/// How many times over its WHO 24-hour guideline a concentration in µg/m³ is, for the
+/// pollutants the WHO defines one for.
+pub fn who_multiple(pollutant: &str, concentration: f64) -> Option<f64> {
+    WHO_24H_GUIDELINES
+        .iter()
+        .find(|(key, _, _)| *key == pollutant)
+        .map(|(_, _, limit)| concentration / limit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sub_index_with_known_breakpoints_is_labelled_a_concentration() {
+        assert_eq!(
+            DisplayValue::of("pm25", 150.0),
+            DisplayValue::Concentration(55.4)
+        );
+        assert_eq!(DisplayValue::of("pm25", 150.0).to_string(), "55.4 µg/m³");
+        assert!(matches!(
+            DisplayValue::of("o3", 50.0),
+            DisplayValue::Concentration(ug) if (ug - 0.054 * O3_UG_PER_PPM).abs() < 1e-9
+        ));
+    }
+
+    #[test]
+    fn sub_index_without_breakpoints_is_labelled_an_index() {
+        assert_eq!(DisplayValue::of("so2", 20.0), DisplayValue::SubIndex(20.0));
+        assert_eq!(DisplayValue::of("co", 7.0).to_string(), "index 7");
+    }
+
+    #[test]
+    fn invalid_sub_index_has_no_concentration() {
+        assert_eq!(pm25(-1.0), None);
+        assert_eq!(pm25(f64::NAN), None);
+    }
+
+    #[test]
+    fn cigarettes_at_a_known_concentration() {
+        assert_eq!(cigarettes(44.0), 2.0);
+        assert_eq!(cigarettes(0.0), 0.0);
+    }
+
+    #[test]
+    fn who_multiple_at_a_known_concentration() {
+        assert_eq!(who_multiple("pm25", 48.0), Some(3.2));
+        assert_eq!(who_multiple("pm10", 90.0), Some(2.0));
+        assert_eq!(who_multiple("o3", 100.0), None);
+    }
+}
@@ -0,0 +1,451 @@
+use serde::Deserialize;
+use std::{
+    fmt::Display,
+    path::PathBuf,
+    str::FromStr,
+    sync::{Arc, RwLock},
+};
+
+const DEFAULT_CONFIG_PATH: &str = "wis.toml";
+
+/// Languages replies can be written in.
+pub const SUPPORTED_LANGUAGES: [&str; 1] = ["en"];
+
+/// Bot configuration, read from an optional TOML file with environment variables taking
+/// precedence over the file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Telegram bot token (`TELOXIDE_TOKEN`).
+    pub telegram_token: String,
+    /// WAQI API token (`AQI_TOKEN`).
+    pub aqi_token: String,
+    /// More WAQI tokens to rotate through alongside `aqi_token` (`AQI_TOKENS`, comma-separated).
+    pub aqi_tokens: Vec<String>,
+    /// WAQI API base URL (`WAQI_BASE_URL`).
+    pub base_url: String,
+    /// Another host serving the same API, tried when `base_url` can't be connected to. Empty
+    /// disables it (`WAQI_FALLBACK_BASE_URL`).
+    pub fallback_base_url: String,
+    /// Language used for replies (`DEFAULT_LANGUAGE`).
+    pub default_language: String,
+    /// How long a WAQI response is reused, 0 disables caching (`CACHE_TTL_SECS`).
+    pub cache_ttl_secs: u64,
+    /// Save the cache to `cache_file` on shutdown and load it back on startup, so a restart
+    /// doesn't start cold (`PERSIST_CACHE`).
+    pub persist_cache: bool,
+    /// Where the cache is persisted (`CACHE_FILE`).
+    pub cache_file: String,
+    /// How long a name that matched no station keeps failing without asking WAQI again
+    /// (`NOT_FOUND_TTL_SECS`).
+    pub not_found_ttl_secs: u64,
+    /// Commands taking longer than this get a "try again" reply (`COMMAND_TIMEOUT_SECS`).
+    pub command_timeout_secs: u64,
+    /// WAQI fetches slower than this are logged and counted (`SLOW_FETCH_MS`).
+    pub slow_fetch_ms: u64,
+    pub rate_limit: RateLimitConfig,
+    /// Pause between the fetches of a single fan-out, 0 sends them back to back
+    /// (`INTER_REQUEST_DELAY_MS`).
+    pub inter_request_delay_ms: u64,
+    /// Cities the digest scheduler fetches at the same time (`SCHEDULER_CONCURRENCY`).
+    pub scheduler_concurrency: usize,
+    /// How far `/cleanest` looks for stations (`CLEANEST_RADIUS_KM`).
+    pub cleanest_radius_km: f64,
+    /// How often the upstream health check runs, 0 disables it (`HEALTH_CHECK_INTERVAL_SECS`).
+    pub health_check_interval_secs: u64,
+    /// City the health check fetches (`HEALTH_CHECK_CITY`).
+    pub health_check_city: String,
+    /// Failures in a row before the admins are alerted (`HEALTH_CHECK_FAILURES`).
+    pub health_check_failures: u32,
+    /// Chats allowed to run admin commands (`ADMIN_CHAT_IDS`, comma-separated).
+    pub admin_chat_ids: Vec<i64>,
+    /// Whether `/version` asks GitHub for the latest release (`CHECK_UPDATES`).
+    pub check_updates: bool,
+    /// Serve bundled sample readings when no WAQI token is set (`DEMO_MODE`).
+    pub demo_mode: bool,
+    /// Port of the `/healthz` and `/readyz` HTTP endpoints, 0 disables them (`STATUS_PORT`).
+    pub status_port: u16,
+    /// Hold `/readyz` back until `health_check_city` has been fetched once
+    /// (`READY_REQUIRES_WAQI`).
+    pub ready_requires_waqi: bool,
+    /// Answer lookups with `maintenance_message` instead of asking WAQI, also switched by
+    /// `/maintenance` (`MAINTENANCE_MODE`).
+    pub maintenance_mode: bool,
+    /// Reply to lookups during maintenance (`MAINTENANCE_MESSAGE`).
+    pub maintenance_message: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    /// Lookups a single chat may make per minute (`RATE_LIMIT_PER_USER`).
+    pub per_user_per_minute: u32,
+    /// Requests the whole bot may send to WAQI per minute (`RATE_LIMIT_GLOBAL`).
+    pub global_per_minute: u32,
+    /// Time a chat has to wait between two runs of the same heavy command, 0 disables the
+    /// cooldown (`RATE_LIMIT_HEAVY_COOLDOWN_SECS`).
+    pub heavy_command_cooldown_secs: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            telegram_token: String::new(),
+            aqi_token: String::new(),
+            aqi_tokens: Vec::new(),
+            base_url: "https://api.waqi.info".into(),
+            fallback_base_url: String::new(),
+            default_language: "en".into(),
+            cache_ttl_secs: 600,
+            persist_cache: false,
+            cache_file: "wis-cache.json".into(),
+            not_found_ttl_secs: 120,
+            command_timeout_secs: 25,
+            slow_fetch_ms: 3000,
+            rate_limit: RateLimitConfig::default(),
+            inter_request_delay_ms: 0,
+            scheduler_concurrency: 10,
+            cleanest_radius_km: 10.0,
+            health_check_interval_secs: 300,
+            health_check_city: "shanghai".into(),
+            health_check_failures: 3,
+            admin_chat_ids: Vec::new(),
+            check_updates: false,
+            demo_mode: false,
+            status_port: 0,
+            ready_requires_waqi: false,
+            maintenance_mode: false,
+            maintenance_message: "🛠️ The bot is under maintenance, lookups will be back soon."
+                .into(),
+        }
+    }
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            per_user_per_minute: 10,
+            global_per_minute: 600,
+            heavy_command_cooldown_secs: 30,
+        }
+    }
+}
+
+impl Config {
+    /// Loads the config file given by `--config <path>` (or `./wis.toml` if present), applies
+    /// environment overrides and validates the result.
+    pub fn load(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let (path, explicit) = match config_path_from_args(args)? {
+            Some(path) => (path, true),
+            None => (PathBuf::from(DEFAULT_CONFIG_PATH), false),
+        };
+
+        let mut config = match std::fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents)
+                .map_err(|e| format!("Failed to parse {}: {e}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && !explicit => Config::default(),
+            Err(e) => return Err(format!("Failed to read {}: {e}", path.display())),
+        };
+
+        config.apply_env()?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn apply_env(&mut self) -> Result<(), String> {
+        env_override("TELOXIDE_TOKEN", &mut self.telegram_token)?;
+        env_override("AQI_TOKEN", &mut self.aqi_token)?;
+        env_list_override("AQI_TOKENS", &mut self.aqi_tokens)?;
+        env_override("WAQI_BASE_URL", &mut self.base_url)?;
+        env_override("WAQI_FALLBACK_BASE_URL", &mut self.fallback_base_url)?;
+        env_override("DEFAULT_LANGUAGE", &mut self.default_language)?;
+        env_override("CACHE_TTL_SECS", &mut self.cache_ttl_secs)?;
+        env_flag_override("PERSIST_CACHE", &mut self.persist_cache)?;
+        env_override("CACHE_FILE", &mut self.cache_file)?;
+        env_override("NOT_FOUND_TTL_SECS", &mut self.not_found_ttl_secs)?;
+        env_override("COMMAND_TIMEOUT_SECS", &mut self.command_timeout_secs)?;
+        env_override("SLOW_FETCH_MS", &mut self.slow_fetch_ms)?;
+        env_override(
+            "RATE_LIMIT_PER_USER",
+            &mut self.rate_limit.per_user_per_minute,
+        )?;
+        env_override("RATE_LIMIT_GLOBAL", &mut self.rate_limit.global_per_minute)?;
+        env_override(
+            "RATE_LIMIT_HEAVY_COOLDOWN_SECS",
+            &mut self.rate_limit.heavy_command_cooldown_secs,
+        )?;
+        env_override("INTER_REQUEST_DELAY_MS", &mut self.inter_request_delay_ms)?;
+        env_override("SCHEDULER_CONCURRENCY", &mut self.scheduler_concurrency)?;
+        env_override("CLEANEST_RADIUS_KM", &mut self.cleanest_radius_km)?;
+        env_override(
+            "HEALTH_CHECK_INTERVAL_SECS",
+            &mut self.health_check_interval_secs,
+        )?;
+        env_override("HEALTH_CHECK_CITY", &mut self.health_check_city)?;
+        env_override("HEALTH_CHECK_FAILURES", &mut self.health_check_failures)?;
+        env_list_override("ADMIN_CHAT_IDS", &mut self.admin_chat_ids)?;
+        env_flag_override("CHECK_UPDATES", &mut self.check_updates)?;
+        env_flag_override("DEMO_MODE", &mut self.demo_mode)?;
+        env_override("STATUS_PORT", &mut self.status_port)?;
+        env_flag_override("READY_REQUIRES_WAQI", &mut self.ready_requires_waqi)?;
+        env_flag_override("MAINTENANCE_MODE", &mut self.maintenance_mode)?;
+        env_override("MAINTENANCE_MESSAGE", &mut self.maintenance_message)?;
+
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.telegram_token.trim().is_empty() {
+            return Err("telegram_token (TELOXIDE_TOKEN) must be set".into());
+        }
+        if self.aqi_tokens().is_empty() && !self.demo_mode {
+            return Err(
+                "aqi_token (AQI_TOKEN) or aqi_tokens (AQI_TOKENS) must be set, or demo_mode \
+                 (DEMO_MODE) turned on"
+                    .into(),
+            );
+        }
+
+        validate_base_url("base_url", &self.base_url)?;
+        if !self.fallback_base_url.trim().is_empty() {
+            validate_base_url("fallback_base_url", &self.fallback_base_url)?;
+        }
+
+        if !SUPPORTED_LANGUAGES.contains(&self.default_language.as_str()) {
+            return Err(format!(
+                "default_language ({}) must be one of: {}",
+                self.default_language,
+                SUPPORTED_LANGUAGES.join(", ")
+            ));
+        }
+
+        if self.cache_ttl_secs > 24 * 60 * 60 {
+            return Err(format!(
+                "cache_ttl_secs ({}) must be at most a day",
+                self.cache_ttl_secs
+            ));
+        }
+
+        // Station names come and go, so a miss shouldn't stick around longer than a hit.
+        if self.cache_ttl_secs > 0 && self.not_found_ttl_secs > self.cache_ttl_secs {
+            return Err(format!(
+                "not_found_ttl_secs ({}) must not exceed cache_ttl_secs ({})",
+                self.not_found_ttl_secs, self.cache_ttl_secs
+            ));
+        }
+
+        if self.command_timeout_secs == 0 {
+            return Err("command_timeout_secs must be greater than 0".into());
+        }
+
+        if self.slow_fetch_ms == 0 {
+            return Err("slow_fetch_ms must be greater than 0".into());
+        }
+
+        if self.rate_limit.per_user_per_minute == 0 {
+            return Err("rate_limit.per_user_per_minute must be greater than 0".into());
+        }
+        if self.rate_limit.global_per_minute == 0 {
+            return Err("rate_limit.global_per_minute must be greater than 0".into());
+        }
+
+        if self.scheduler_concurrency == 0 {
+            return Err("scheduler_concurrency must be greater than 0".into());
+        }
+
+        if !(self.cleanest_radius_km > 0.0 && self.cleanest_radius_km <= 100.0) {
+            return Err(format!(
+                "cleanest_radius_km ({}) must be above 0 and at most 100",
+                self.cleanest_radius_km
+            ));
+        }
+
+        if self.health_check_interval_secs > 0 {
+            if self.health_check_city.trim().is_empty() {
+                return Err("health_check_city must be set".into());
+            }
+            if self.health_check_failures == 0 {
+                return Err("health_check_failures must be greater than 0".into());
+            }
+        }
+
+        if self.maintenance_message.trim().is_empty() {
+            return Err("maintenance_message must not be empty".into());
+        }
+
+        if self.ready_requires_waqi && self.health_check_city.trim().is_empty() {
+            return Err("health_check_city must be set for ready_requires_waqi".into());
+        }
+
+        Ok(())
+    }
+
+    /// Whether the bot serves sample data: demo mode is on and there's no token to use instead.
+    pub fn is_demo(&self) -> bool {
+        self.demo_mode && self.aqi_tokens().is_empty()
+    }
+
+    /// Every configured WAQI token, `aqi_token` first, without blanks or duplicates.
+    pub fn aqi_tokens(&self) -> Vec<String> {
+        let mut tokens: Vec<String> = Vec::new();
+        for token in std::iter::once(&self.aqi_token).chain(&self.aqi_tokens) {
+            let token = token.trim();
+            if !token.is_empty() && !tokens.iter().any(|t| t == token) {
+                tokens.push(token.to_owned());
+            }
+        }
+        tokens
+    }
+
+    pub fn is_admin(&self, chat_id: i64) -> bool {
+        self.admin_chat_ids.contains(&chat_id)
+    }
+
+    /// `base_url` without a trailing slash, ready to have paths appended.
+    pub fn api_base(&self) -> &str {
+        self.base_url.trim_end_matches('/')
+    }
+
+    /// `fallback_base_url` like `api_base`, if one is set.
+    pub fn api_fallback_base(&self) -> Option<&str> {
+        let base = self.fallback_base_url.trim().trim_end_matches('/');
+        (!base.is_empty()).then_some(base)
+    }
+}
+
+/// The configuration in use, which `/reload` can replace while the bot is running.
+pub struct SharedConfig {
+    current: RwLock<Arc<Config>>,
+    /// Command line arguments, to find the same config file again.
+    args: Vec<String>,
+}
+
+impl SharedConfig {
+    pub fn new(config: Config, args: Vec<String>) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(config)),
+            args,
+        }
+    }
+
+    pub fn current(&self) -> Arc<Config> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Switches maintenance mode on or off until the next `/reload`.
+    pub fn set_maintenance(&self, enabled: bool) {
+        let mut current = self.current.write().unwrap();
+        let mut config = Config::clone(&current);
+        config.maintenance_mode = enabled;
+        *current = Arc::new(config);
+    }
+
+    /// Loads the configuration again and swaps it in, returning the old and the new one.
+    /// Settings that only take effect on startup keep their running values.
+    pub fn reload(&self) -> Result<(Arc<Config>, Arc<Config>), String> {
+        let mut loaded = Config::load(self.args.iter().cloned())?;
+        let mut current = self.current.write().unwrap();
+        let old = current.clone();
+
+        let requested = loaded.clone();
+        loaded.telegram_token.clone_from(&old.telegram_token);
+        loaded.aqi_token.clone_from(&old.aqi_token);
+        loaded.aqi_tokens.clone_from(&old.aqi_tokens);
+        loaded.base_url.clone_from(&old.base_url);
+        loaded.fallback_base_url.clone_from(&old.fallback_base_url);
+        loaded.scheduler_concurrency = old.scheduler_concurrency;
+        loaded.health_check_interval_secs = old.health_check_interval_secs;
+        loaded.status_port = old.status_port;
+        loaded.ready_requires_waqi = old.ready_requires_waqi;
+        loaded.persist_cache = old.persist_cache;
+        loaded.cache_file.clone_from(&old.cache_file);
+
+        *current = Arc::new(loaded);
+        Ok((old, Arc::new(requested)))
+    }
+}
+
+/// Checks that the URL of the setting `name` is one WAQI can be reached at.
+fn validate_base_url(name: &str, value: &str) -> Result<(), String> {
+    let url = reqwest::Url::parse(value)
+        .map_err(|e| format!("{name} ({value}) is not a valid URL: {e}"))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!("{name} ({value}) must be http or https"));
+    }
+    Ok(())
+}
+
+fn config_path_from_args(
+    mut args: impl Iterator<Item = String>,
+) -> Result<Option<PathBuf>, String> {
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            return args
+                .next()
+                .map(|path| Some(PathBuf::from(path)))
+                .ok_or_else(|| "--config requires a path".into());
+        }
+        if let Some(path) = arg.strip_prefix("--config=") {
+            return Ok(Some(PathBuf::from(path)));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Replaces `target` with the value of the environment variable `name` if it is set and not empty.
+fn env_override<T>(name: &str, target: &mut T) -> Result<(), String>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    match std::env::var(name) {
+        Ok(value) if !value.trim().is_empty() => {
+            *target = value
+                .trim()
+                .parse()
+                .map_err(|e| format!("{name} ({value}) is invalid: {e}"))?;
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Like `env_override`, for flags set with `1`/`0` as well as `true`/`false`.
+fn env_flag_override(name: &str, target: &mut bool) -> Result<(), String> {
+    match std::env::var(name) {
+        Ok(value) if !value.trim().is_empty() => {
+            *target = match value.trim().to_ascii_lowercase().as_str() {
+                "1" | "true" | "yes" | "on" => true,
+                "0" | "false" | "no" | "off" => false,
+                _ => return Err(format!("{name} ({value}) is invalid: expected 1 or 0")),
+            };
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Like `env_override`, for comma-separated lists.
+fn env_list_override<T>(name: &str, target: &mut Vec<T>) -> Result<(), String>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    match std::env::var(name) {
+        Ok(value) if !value.trim().is_empty() => {
+            *target = value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(|item| {
+                    item.parse()
+                        .map_err(|e| format!("{name} ({item}) is invalid: {e}"))
+                })
+                .collect::<Result<_, _>>()?;
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,31 @@
+use crate::input;
+
+/// Start payloads that open the bot on a city's reading.
+const CITY_PREFIX: &str = "city_";
+
+/// Longest start payload Telegram accepts.
+const MAX_PAYLOAD_LEN: usize = 64;
+
+/// A `t.me` link that opens the bot and shows the reading of `city`. The city is hex encoded
+/// since start payloads may only hold `A-Z`, `a-z`, `0-9`, `_` and `-`. Names too long to fit
+/// the payload get no link.
+pub fn city_link(bot_username: &str, city: &str) -> Option<String> {
+    let encoded: String = city.bytes().map(|b| format!("{b:02x}")).collect();
+    let payload = format!("{CITY_PREFIX}{encoded}");
+    (payload.len() <= MAX_PAYLOAD_LEN)
+        .then(|| format!("https://t.me/{bot_username}?start={payload}"))
+}
+
+/// The city of a `/start` payload made by `city_link`, if it is one.
+pub fn city_from_payload(payload: &str) -> Option<String> {
+    let encoded = payload.trim().strip_prefix(CITY_PREFIX)?;
+    if encoded.len() % 2 != 0 || !encoded.is_ascii() {
+        return None;
+    }
+    let bytes = (0..encoded.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&encoded[i..i + 2], 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    let city = input::clean_city(&String::from_utf8(bytes).ok()?);
+    (!city.is_empty()).then_some(city)
+}
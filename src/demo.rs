@@ -0,0 +1,30 @@
+use crate::model::{parse_envelope, Envelope, PollutionData};
+
+/// Bundled sample readings served when the bot runs without a WAQI token.
+const FIXTURES: [(&str, &str); 3] = [
+    ("london", include_str!("../assets/demo/london.json")),
+    ("shanghai", include_str!("../assets/demo/shanghai.json")),
+    ("tehran", include_str!("../assets/demo/tehran.json")),
+];
+
+/// The bundled reading of `city`, labelled as sample data so it isn't mistaken for a live one.
+pub fn feed(city: &str) -> Option<PollutionData> {
+    let city = city.to_lowercase();
+    let (_, fixture) = FIXTURES.iter().find(|(name, _)| *name == city)?;
+    let Ok(Envelope::Ok(data)) = parse_envelope::<PollutionData>(fixture) else {
+        tracing::error!("Bundled demo fixture of {city} is invalid");
+        return None;
+    };
+    let mut data = *data;
+    data.city.name = format!("{} (sample data)", data.city.name);
+    Some(data)
+}
+
+/// Names of the cities with a bundled reading, for telling users what they can look up.
+pub fn cities() -> String {
+    FIXTURES
+        .iter()
+        .map(|(name, _)| name.to_owned())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -0,0 +1,57 @@
+use crate::{config::SharedConfig, waqi::Waqi, Bot};
+use std::{sync::Arc, time::Duration};
+use teloxide::prelude::*;
+
+/// Starts the background task fetching `health_check_city` every `health_check_interval_secs`
+/// and telling the admins once it has failed `health_check_failures` times in a row, and
+/// again when it recovers. Does nothing if the interval is 0.
+pub fn spawn(bot: Bot, waqi: Waqi, config: Arc<SharedConfig>) {
+    let interval = config.current().health_check_interval_secs;
+    if interval == 0 {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut failures = 0;
+        let mut alerted = false;
+        loop {
+            tokio::time::sleep(Duration::from_secs(interval)).await;
+
+            let current = config.current();
+            match waqi.feed_uncached(&current.health_check_city).await {
+                Ok(_) => {
+                    if alerted {
+                        notify(
+                            &bot,
+                            &current.admin_chat_ids,
+                            "✅ WAQI is responding again.",
+                        )
+                        .await;
+                    }
+                    failures = 0;
+                    alerted = false;
+                }
+                Err(e) => {
+                    failures += 1;
+                    tracing::warn!(failures, "Health check failed: {e}");
+                    if failures >= current.health_check_failures && !alerted {
+                        let text = format!(
+                            "🚨 WAQI appears to be down: fetching {} failed {failures} times in a row.\nLast error: {e}",
+                            current.health_check_city
+                        );
+                        notify(&bot, &current.admin_chat_ids, &text).await;
+                        alerted = true;
+                    }
+                }
+            }
+        }
+    });
+}
+
+async fn notify(bot: &Bot, admins: &[i64], text: &str) {
+    for admin in admins {
+        if let Err(e) = bot.send_message(ChatId(*admin), text).await {
+            tracing::warn!("Failed to alert {admin}: {e}");
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use crate::{
+    ascii::ChatBot,
+    config::SharedConfig,
+    render_report,
+    store::{Store, Verbosity},
+    waqi::Waqi,
+    Bot, UserLimiter,
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use teloxide::{
+    prelude::*,
+    types::{
+        InlineQueryResult, InlineQueryResultArticle, InputMessageContent, InputMessageContentText,
+        UserId,
+    },
+};
+
+/// How long a query has to stay unchanged before it's looked up.
+const SETTLE_DELAY: Duration = Duration::from_millis(700);
+
+/// How long Telegram may reuse an answer for the same query.
+const CACHE_TIME_SECS: u32 = 300;
+
+/// Inline queries fire on every keystroke, so only the latest query of each user that has been
+/// left alone for `SETTLE_DELAY` gets fetched.
+#[derive(Default)]
+pub struct Debouncer {
+    latest: Mutex<HashMap<UserId, String>>,
+}
+
+impl Debouncer {
+    /// Waits out `SETTLE_DELAY`, returning whether `query_id` is still the user's latest query.
+    async fn settle(&self, user: UserId, query_id: &str) -> bool {
+        self.latest
+            .lock()
+            .unwrap()
+            .insert(user, query_id.to_owned());
+        tokio::time::sleep(SETTLE_DELAY).await;
+
+        let mut latest = self.latest.lock().unwrap();
+        if latest.get(&user).is_some_and(|id| id == query_id) {
+            latest.remove(&user);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Answers `@bot city` with the city's report, ready to be sent into any chat.
+pub async fn answer_inline(
+    bot: Bot,
+    query: InlineQuery,
+    store: Store,
+    waqi: Waqi,
+    user_limiter: UserLimiter,
+    debouncer: Arc<Debouncer>,
+    shared_config: Arc<SharedConfig>,
+) -> ResponseResult<()> {
+    let city = query.query.trim();
+    if city.chars().count() < 2 || !debouncer.settle(query.from.id, &query.id).await {
+        return Ok(());
+    }
+
+    let config = shared_config.current();
+    if config.maintenance_mode {
+        let article = InlineQueryResultArticle::new(
+            "maintenance",
+            config.maintenance_message.clone(),
+            InputMessageContent::Text(InputMessageContentText::new(
+                config.maintenance_message.clone(),
+            )),
+        );
+        // Not cached, so lookups work again as soon as maintenance is over.
+        bot.answer_inline_query(&query.id, [InlineQueryResult::Article(article)])
+            .cache_time(0)
+            .await?;
+        return Ok(());
+    }
+
+    // Settings are stored per chat, and a user's private chat has the same id as the user.
+    let chat_id = ChatId::from(query.from.id);
+    if !user_limiter.lookups.try_acquire(chat_id) {
+        return Ok(());
+    }
+    let settings = store.settings(chat_id).await;
+    // The user sends the article themselves, but it should still read the way they set it up.
+    let bot = ChatBot::new(bot, &settings);
+
+    let mut results = Vec::new();
+    match waqi.feed_or_closest(city).await {
+        Ok((data, _)) => match render_report(&data, &settings) {
+            Ok(report) => {
+                let mut brief = settings.clone();
+                brief.verbosity = Verbosity::Brief;
+                let description = bot.text(render_report(&data, &brief).unwrap_or_default());
+
+                let article = InlineQueryResultArticle::new(
+                    data.idx.to_string(),
+                    data.city.name.clone(),
+                    InputMessageContent::Text(InputMessageContentText::new(bot.text(report))),
+                )
+                .description(description);
+                results.push(InlineQueryResult::Article(article));
+            }
+            Err(e) => tracing::warn!("{e}"),
+        },
+        Err(e) => tracing::warn!("{e}"),
+    }
+
+    bot.answer_inline_query(&query.id, results)
+        .cache_time(CACHE_TIME_SECS)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,37 @@
+use unicode_normalization::UnicodeNormalization;
+
+/// Invisible formatting characters clients like to slip into text: zero-width spaces and
+/// joiners, direction marks, bidi embeddings/overrides/isolates and the BOM.
+fn is_invisible_format(c: char) -> bool {
+    matches!(
+        c,
+        '\u{200B}'..='\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2060}'..='\u{2069}' | '\u{FEFF}'
+    )
+}
+
+/// Normalizes a city name typed by a user: NFC, without invisible formatting or control
+/// characters, single-spaced. Emoji are kept, WAQI simply won't match them.
+pub fn clean_city(input: &str) -> String {
+    let cleaned: String = input
+        .nfc()
+        .filter(|c| !is_invisible_format(*c) && !c.is_control())
+        .collect();
+    cleaned.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rtl_marks_are_stripped() {
+        assert_eq!(clean_city("\u{200F}تهران\u{200E}"), "تهران");
+        assert_eq!(clean_city("\u{202B}Tel\u{200B} Aviv\u{202C}"), "Tel Aviv");
+    }
+
+    #[test]
+    fn emoji_are_kept_and_names_composed() {
+        assert_eq!(clean_city("  Tehran   🌆 "), "Tehran 🌆");
+        assert_eq!(clean_city("Zu\u{308}rich"), "Z\u{fc}rich");
+    }
+}
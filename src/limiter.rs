@@ -0,0 +1,110 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    hash::Hash,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// Sliding-window rate limiter allowing `limit` hits per key within `window`.
+pub struct RateLimiter<K> {
+    limit: AtomicU32,
+    window: Duration,
+    hits: Mutex<HashMap<K, VecDeque<Instant>>>,
+}
+
+impl<K: Eq + Hash> RateLimiter<K> {
+    pub fn new(limit: u32, window: Duration) -> Self {
+        Self {
+            limit: AtomicU32::new(limit),
+            window,
+            hits: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn per_minute(limit: u32) -> Self {
+        Self::new(limit, Duration::from_secs(60))
+    }
+
+    pub fn set_limit(&self, limit: u32) {
+        self.limit.store(limit, Ordering::Relaxed);
+    }
+
+    pub fn limit(&self) -> u32 {
+        self.limit.load(Ordering::Relaxed)
+    }
+
+    /// Hits `key` has left in the current window.
+    pub fn remaining(&self, key: &K) -> u32 {
+        let now = Instant::now();
+        let hits = self.hits.lock().unwrap();
+        let recent = hits.get(key).map_or(0, |times| {
+            times
+                .iter()
+                .filter(|hit| now.duration_since(**hit) < self.window)
+                .count()
+        });
+        self.limit().saturating_sub(recent as u32)
+    }
+
+    /// Records a hit for `key`, returning `false` (and recording nothing) if it's over the limit.
+    pub fn try_acquire(&self, key: K) -> bool {
+        let now = Instant::now();
+        let mut hits = self.hits.lock().unwrap();
+        hits.retain(|_, times| {
+            while times
+                .front()
+                .is_some_and(|hit| now.duration_since(*hit) >= self.window)
+            {
+                times.pop_front();
+            }
+            !times.is_empty()
+        });
+
+        let times = hits.entry(key).or_default();
+        if times.len() >= self.limit.load(Ordering::Relaxed) as usize {
+            return false;
+        }
+        times.push_back(now);
+        true
+    }
+}
+
+/// Lets each key start something at most once per cooldown. The cooldown is passed on every
+/// call so it can change at runtime.
+pub struct Cooldown<K> {
+    started: Mutex<HashMap<K, Instant>>,
+}
+
+impl<K: Eq + Hash> Default for Cooldown<K> {
+    fn default() -> Self {
+        Self {
+            started: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<K: Eq + Hash> Cooldown<K> {
+    /// How long until `key` may start again, `None` if it may right away.
+    pub fn remaining(&self, key: &K, cooldown: Duration) -> Option<Duration> {
+        let started = self.started.lock().unwrap();
+        let elapsed = started.get(key)?.elapsed();
+        cooldown.checked_sub(elapsed).filter(|left| !left.is_zero())
+    }
+
+    /// Records a start for `key`, returning `false` (and recording nothing) if the previous one
+    /// was less than `cooldown` ago.
+    pub fn try_start(&self, key: K, cooldown: Duration) -> bool {
+        let now = Instant::now();
+        let mut started = self.started.lock().unwrap();
+        started.retain(|_, start| now.duration_since(*start) < cooldown);
+
+        if started.contains_key(&key) {
+            return false;
+        }
+        started.insert(key, now);
+        true
+    }
+}
@@ -1,230 +1,4649 @@
-use aqi::{co, no2, ozone8, pm10, pm2_5, so2_1, AirQuality, AirQualityLevel};
-use serde::Deserialize;
-use std::{collections::HashMap, time::Duration};
-use teloxide::{prelude::*, utils::command::BotCommands};
-use tokio::time::timeout;
+mod ascii;
+mod broadcast;
+mod cache;
+mod caqi;
+mod chart;
+mod concentration;
+mod config;
+mod deeplink;
+mod demo;
+mod health;
+mod inline;
+mod input;
+mod limiter;
+mod model;
+mod pages;
+mod replies;
+mod scheduler;
+mod settings_menu;
+mod status;
+mod store;
+mod tokens;
+mod version;
+mod waqi;
+
+use aqi::{AirQuality, AirQualityLevel};
+use ascii::ChatBot;
+use cache::TtlCache;
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime, Utc};
+use chrono_tz::Tz;
+use concentration::DisplayValue;
+use config::{Config, SharedConfig};
+use limiter::{Cooldown, RateLimiter};
+use model::{
+    normalize_pollutant, HistoricalDay, IaqiValue, MapStation, PollutionData, Time,
+    POLLUTANT_ALIASES,
+};
+use replies::Replies;
+use std::{
+    collections::HashMap,
+    ops::RangeInclusive,
+    path::PathBuf,
+    sync::{Arc, LazyLock},
+    time::Duration,
+};
+use store::{
+    AlertMode, DigestLayout, Sample, Standard, Store, Subscription, Target, TimeFormat, UserExport,
+    UserSettings, ValueMode, Verbosity, MAX_FORECAST_DAYS,
+};
+use teloxide::{
+    adaptors::{throttle::Limits, Throttle},
+    dispatching::ShutdownToken,
+    net::Download,
+    prelude::*,
+    types::{InlineKeyboardMarkup, InputFile, LinkPreviewOptions, Me, MessageId, ParseMode},
+    utils::{command::BotCommands, html},
+    ApiError, RequestError,
+};
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::Semaphore,
+};
+use tracing_subscriber::EnvFilter;
+use waqi::{Probe, Waqi, WaqiError};
+
+/// The bot every part of the crate sends through. Requests are queued to stay within
+/// Telegram's flood limits (per chat and overall), so bursts wait instead of getting 429s.
+pub type Bot = Throttle<teloxide::Bot>;
+
+// ------------------- //
+// BEGIN Bot Commands  //
+// ------------------- //
+
+#[tokio::main]
+async fn main() {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+        )
+        .init();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let config = Config::load(args.iter().cloned()).unwrap_or_else(|e| {
+        eprintln!("Invalid configuration: {e}");
+        std::process::exit(1);
+    });
+
+    if config.maintenance_mode {
+        tracing::warn!("Maintenance mode: lookups are answered with the maintenance message");
+    }
+
+    if config.is_demo() {
+        tracing::warn!(
+            "Demo mode: no WAQI token set, only sample data of {} is served",
+            demo::cities()
+        );
+    }
+
+    let bot = teloxide::Bot::with_client(&config.telegram_token, teloxide::net::client_from_env())
+        .throttle(Limits::default());
+
+    let data_file = std::env::var("DATA_FILE").unwrap_or_else(|_| "wis-data.json".into());
+    let store = Store::load(data_file).unwrap_or_else(|e| panic!("{e}"));
+    let waqi = Waqi::new(&config);
+    let persisted_cache =
+        (config.persist_cache && !config.is_demo()).then(|| PathBuf::from(&config.cache_file));
+    if let Some(path) = &persisted_cache {
+        match waqi.load_cache(path) {
+            Ok(loaded) => tracing::info!("Loaded {loaded} cached feeds from {}", path.display()),
+            Err(e) => tracing::warn!("Starting with an empty cache: {e}"),
+        }
+    }
+    let user_limiter: UserLimiter = Arc::new(Limiters {
+        lookups: RateLimiter::per_minute(config.rate_limit.per_user_per_minute),
+        feedback: RateLimiter::per_minute(FEEDBACK_PER_MINUTE),
+        heavy: Cooldown::default(),
+    });
+
+    scheduler::spawn(
+        bot.clone(),
+        store.clone(),
+        waqi.clone(),
+        config.scheduler_concurrency,
+    );
+    status::spawn(
+        config.status_port,
+        bot.clone(),
+        waqi.clone(),
+        config.health_check_city.clone(),
+        config.ready_requires_waqi,
+    );
+    let config = Arc::new(SharedConfig::new(config, args));
+    health::spawn(bot.clone(), waqi.clone(), config.clone());
+
+    let debouncer = Arc::new(inline::Debouncer::default());
+    let replies = Arc::new(Replies::default());
+
+    let handler = dptree::entry()
+        .branch(
+            Update::filter_message()
+                .filter_command::<Command>()
+                .endpoint(answer),
+        )
+        .branch(
+            Update::filter_edited_message()
+                .filter_command::<Command>()
+                .endpoint(answer_edit),
+        )
+        .branch(Update::filter_inline_query().endpoint(inline::answer_inline))
+        .branch(
+            Update::filter_callback_query()
+                .filter(|query: CallbackQuery| {
+                    query
+                        .data
+                        .as_deref()
+                        .is_some_and(|data| data.starts_with(WORLD_CALLBACK_PREFIX))
+                })
+                .endpoint(answer_world_page),
+        )
+        .branch(Update::filter_callback_query().endpoint(settings_menu::answer_callback));
+
+    let mut dispatcher = Dispatcher::builder(bot, handler)
+        .dependencies(dptree::deps![
+            store,
+            waqi.clone(),
+            user_limiter,
+            config,
+            debouncer,
+            replies
+        ])
+        .enable_ctrlc_handler()
+        .build();
+    spawn_sigterm_handler(dispatcher.shutdown_token());
+    dispatcher.dispatch().await;
+
+    if let Some(path) = &persisted_cache {
+        match waqi.save_cache(path) {
+            Ok(saved) => tracing::info!("Saved {saved} cached feeds to {}", path.display()),
+            Err(e) => tracing::error!("Failed to save the cache: {e}"),
+        }
+    }
+}
+
+/// Shuts the dispatcher down on SIGTERM like Ctrl-C does, so `docker stop` also lets `main`
+/// save the cache before exiting.
+fn spawn_sigterm_handler(shutdown_token: ShutdownToken) {
+    let mut sigterm = match signal(SignalKind::terminate()) {
+        Ok(sigterm) => sigterm,
+        Err(e) => {
+            tracing::warn!("Can't listen for SIGTERM: {e}");
+            return;
+        }
+    };
+    tokio::spawn(async move {
+        sigterm.recv().await;
+        tracing::info!("SIGTERM received, shutting down");
+        match shutdown_token.shutdown() {
+            Ok(shutdown) => shutdown.await,
+            Err(e) => tracing::warn!("Couldn't shut down the dispatcher: {e}"),
+        }
+    });
+}
+
+#[derive(BotCommands, Clone)]
+#[command(
+    rename_rule = "lowercase",
+    description = "These commands are supported:"
+)]
+enum Command {
+    #[command(description = "start the bot.")]
+    Start { payload: String },
+    #[command(description = "display this text.")]
+    Help,
+    #[command(description = "get pollution data for a city.")]
+    Wis { city: String },
+    #[command(description = "get pollution data near coordinates or a Google Maps link.")]
+    Nearby { location: String },
+    #[command(description = "find the cleanest air around coordinates or a maps link.")]
+    Cleanest { location: String },
+    #[command(description = "set how much detail to show: brief, normal or detailed.")]
+    Verbosity { level: String },
+    #[command(description = "headline the AQI or the concentration: aqi or concentration.")]
+    ValueMode { mode: String },
+    #[command(description = "show a city's reading in every verbosity and value mode.")]
+    Preview { city: String },
+    #[command(description = "show which pollutants and extras a station reports.")]
+    Coverage { city: String },
+    #[command(description = "list the agencies a city's data comes from.")]
+    Sources { city: String },
+    #[command(description = "show the forecast of a city as a calendar of the week.")]
+    Calendar { city: String },
+    #[command(description = "compare now with tomorrow to plan when to go out.")]
+    Plan { city: String },
+    #[command(description = "get the forecast of a city as a heatmap image.")]
+    Heatmap { city: String },
+    #[command(description = "get the current reading of a city as an image to share.")]
+    Card { city: String },
+    #[command(description = "average the air of a few cities, e.g. /avg tehran, karaj.")]
+    Avg { cities: String },
+    #[command(description = "get a daily digest of a city at an hour (0-23).")]
+    Subscribe { args: String },
+    #[command(description = "list your subscriptions.")]
+    Subscriptions,
+    #[command(description = "stop the daily digest of a city.")]
+    Unsubscribe { city: String },
+    #[command(description = "stop a subscription by its number in /subscriptions.")]
+    Unsub { index: String },
+    #[command(description = "send your daily digest right now.")]
+    TestDigest,
+    #[command(description = "get a digest of several cities as one message or one per city.")]
+    DigestLayout { layout: String },
+    #[command(description = "show times on a 12h or a 24h clock.")]
+    TimeFormat { format: String },
+    #[command(description = "always headline a pollutant (e.g. pm25), or \"off\".")]
+    Focus { pollutant: String },
+    #[command(description = "show the cigarettes-per-day equivalent of PM2.5: on or off.")]
+    Cigarettes { toggle: String },
+    #[command(description = "include the forecast in /wis: on or off.")]
+    WisForecast { toggle: String },
+    #[command(description = "mark readings in the upper half of their band: on or off.")]
+    BandMarkers { toggle: String },
+    #[command(description = "start reports with the ladder of band emoji: on or off.")]
+    Ladder { toggle: String },
+    #[command(description = "write reports without emoji: on or off.")]
+    Ascii { toggle: String },
+    #[command(description = "show the weather conditions in detailed reports: on or off.")]
+    Conditions { toggle: String },
+    #[command(description = "use your own band cutoffs (e.g. 40 80 120 160 250), or \"off\".")]
+    Bands { cutoffs: String },
+    #[command(description = "compare a city's reading on the US EPA and EU CAQI scales.")]
+    Standards { city: String },
+    #[command(description = "grade reports on the US EPA or EU CAQI scale: us, eu or auto.")]
+    Standard { standard: String },
+    #[command(description = "show the forecast of every pollutant and the UV index of a city.")]
+    Forecast { city: String },
+    #[command(description = "compare a city's AQI today with its recent average.")]
+    VsAverage { city: String },
+    #[command(description = "get a city's reading on a past day (YYYY-MM-DD or yesterday).")]
+    Past { args: String },
+    #[command(description = "check how close recent forecasts of a city came to its readings.")]
+    Accuracy { city: String },
+    #[command(description = "show the air quality of a random major city.")]
+    Random,
+    #[command(description = "rank the air of major world capitals.")]
+    World,
+    #[command(description = "list every supported pollutant of a city with its value and AQI.")]
+    Pollutants { city: String },
+    #[command(description = "explain how a city's dominant pollutant and AQI come about.")]
+    Explain { city: String },
+    #[command(description = "how many forecast days to show (1-7), or \"all\".")]
+    ForecastDays { days: String },
+    #[command(
+        description = "set the time zone of your digest hours (e.g. Europe/Berlin), or \"off\"."
+    )]
+    Timezone { timezone: String },
+    #[command(description = "warn about readings older than a duration (e.g. 2h), or \"off\".")]
+    StaleAfter { duration: String },
+    #[command(description = "pause scheduled messages for a while (e.g. 7d).")]
+    Mute { duration: String },
+    #[command(description = "resume scheduled messages.")]
+    Unmute,
+    #[command(description = "show the ways to name a place so lookups find it.")]
+    Tips,
+    #[command(description = "check that your client displays the bot's emoji correctly.")]
+    EmojiTest,
+    #[command(description = "send feedback or a bug report to the bot's admins.")]
+    Feedback { text: String },
+    #[command(description = "show how many lookups you have left and your cooldowns.")]
+    Limits,
+    #[command(description = "show the version the bot is running.")]
+    Version,
+    #[command(description = "show your settings and change them with buttons.")]
+    Settings,
+    #[command(description = "download your settings and subscriptions as a file.")]
+    ExportSettings,
+    #[command(description = "restore settings from an exported file (reply to it).")]
+    ImportSettings,
+    #[command(hide)]
+    Stats,
+    #[command(hide)]
+    Debug,
+    #[command(hide)]
+    Reload,
+    #[command(hide)]
+    Broadcast { text: String },
+    #[command(hide)]
+    AlertRegion { args: String },
+    #[command(hide)]
+    Maintenance { toggle: String },
+    #[command(hide)]
+    Probe { target: String },
+}
+
+/// Most cities a single chat can subscribe to.
+const MAX_SUBSCRIPTIONS: usize = 5;
+
+/// Per-chat budgets.
+struct Limiters {
+    /// Commands that hit WAQI.
+    lookups: RateLimiter<ChatId>,
+    /// `/feedback` messages forwarded to the admins.
+    feedback: RateLimiter<ChatId>,
+    /// Commands that fan out to many stations or fetches, see `Command::heavy_name`.
+    heavy: Cooldown<(ChatId, &'static str)>,
+}
+
+type UserLimiter = Arc<Limiters>;
+
+/// The current AQI of each of `WORLD_CAPITALS`, `Err` where it couldn't be fetched.
+type WorldReadings = Vec<(&'static str, Result<AirQuality, String>)>;
+
+/// `/world` readings, shared by everyone for `WORLD_CACHE_TTL`.
+static WORLD_CACHE: LazyLock<TtlCache<WorldReadings>> =
+    LazyLock::new(|| TtlCache::new(WORLD_CACHE_TTL));
+
+/// How long a `/world` overview is reused. Short, since it's the same for everyone.
+const WORLD_CACHE_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// Commands with a cooldown of their own, as named by `Command::heavy_name`.
+const HEAVY_COMMANDS: [&str; 4] = ["cleanest", "heatmap", "testdigest", "avg"];
+
+/// Most cities a single `/avg` fetches.
+const MAX_AVG_CITIES: usize = 10;
+
+/// Feedback messages a single chat may send per minute.
+const FEEDBACK_PER_MINUTE: u32 = 1;
+
+impl Command {
+    /// Whether the command looks something up, which maintenance mode holds back. Settings,
+    /// help and admin commands keep working.
+    fn is_lookup(&self) -> bool {
+        match self {
+            Command::Start { payload } => deeplink::city_from_payload(payload).is_some(),
+            Command::Wis { .. }
+            | Command::Nearby { .. }
+            | Command::Cleanest { .. }
+            | Command::Preview { .. }
+            | Command::Sources { .. }
+            | Command::Coverage { .. }
+            | Command::Calendar { .. }
+            | Command::Plan { .. }
+            | Command::Heatmap { .. }
+            | Command::Card { .. }
+            | Command::Avg { .. }
+            | Command::Subscribe { .. }
+            | Command::TestDigest
+            | Command::Standards { .. }
+            | Command::Forecast { .. }
+            | Command::VsAverage { .. }
+            | Command::Past { .. }
+            | Command::Accuracy { .. }
+            | Command::Random
+            | Command::World
+            | Command::Pollutants { .. }
+            | Command::Explain { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Name of the command if it's expensive enough to get a cooldown of its own on top of the
+    /// lookup rate limit.
+    fn heavy_name(&self) -> Option<&'static str> {
+        match self {
+            Command::Cleanest { .. } => Some("cleanest"),
+            Command::Heatmap { .. } => Some("heatmap"),
+            Command::TestDigest => Some("testdigest"),
+            Command::Avg { .. } => Some("avg"),
+            _ => None,
+        }
+    }
+}
+
+/// Runs the command, telling the user instead of going quiet when it takes longer than
+/// `command_timeout_secs`. Heavy commands are refused while they're cooling down.
+#[allow(clippy::too_many_arguments)]
+async fn answer(
+    bot: Bot,
+    msg: Message,
+    cmd: Command,
+    store: Store,
+    waqi: Waqi,
+    user_limiter: UserLimiter,
+    shared_config: Arc<SharedConfig>,
+    me: Me,
+    replies: Arc<Replies>,
+) -> ResponseResult<()> {
+    let chat_id = msg.chat.id;
+    let config = shared_config.current();
+    let settings = store.settings(chat_id).await;
+    let bot = ChatBot::new(bot, &settings);
+    if config.maintenance_mode && cmd.is_lookup() {
+        bot.send_message(chat_id, config.maintenance_message.as_str())
+            .await?;
+        return Ok(());
+    }
+    if let Some(name) = cmd.heavy_name() {
+        let cooldown = Duration::from_secs(config.rate_limit.heavy_command_cooldown_secs);
+        if !user_limiter.heavy.try_start((chat_id, name), cooldown) {
+            bot.send_message(chat_id, "Please wait before running that again.")
+                .await?;
+            return Ok(());
+        }
+    }
+
+    let limit = Duration::from_secs(config.command_timeout_secs);
+    let handler = handle_command(
+        bot.clone(),
+        msg,
+        cmd,
+        settings,
+        store,
+        waqi,
+        user_limiter,
+        shared_config,
+        me,
+        replies,
+    );
+    match tokio::time::timeout(limit, handler).await {
+        Ok(result) => result,
+        Err(_) => {
+            tracing::warn!("Command in {chat_id} timed out after {}s", limit.as_secs());
+            bot.send_message(chat_id, "This is taking too long, please try again.")
+                .await?;
+            Ok(())
+        }
+    }
+}
+
+/// Runs a command again after its message was edited, e.g. to fix a typo in a city, replacing
+/// the bot's earlier reply to it. Replies that weren't tracked, like usage hints or the ones sent
+/// before a restart, stay where they are.
+#[allow(clippy::too_many_arguments)]
+async fn answer_edit(
+    bot: Bot,
+    msg: Message,
+    cmd: Command,
+    store: Store,
+    waqi: Waqi,
+    user_limiter: UserLimiter,
+    shared_config: Arc<SharedConfig>,
+    me: Me,
+    replies: Arc<Replies>,
+) -> ResponseResult<()> {
+    if let Some(reply) = replies.take(msg.chat.id, msg.id) {
+        // The new reply may not even be text, so the old one goes rather than being edited.
+        if let Err(e) = bot.delete_message(msg.chat.id, reply).await {
+            tracing::warn!("Failed to delete the reply to an edited command: {e}");
+        }
+    }
+    answer(
+        bot,
+        msg,
+        cmd,
+        store,
+        waqi,
+        user_limiter,
+        shared_config,
+        me,
+        replies,
+    )
+    .await
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn handle_command(
+    bot: ChatBot,
+    msg: Message,
+    cmd: Command,
+    settings: UserSettings,
+    store: Store,
+    waqi: Waqi,
+    user_limiter: UserLimiter,
+    shared_config: Arc<SharedConfig>,
+    me: Me,
+    replies: Arc<Replies>,
+) -> ResponseResult<()> {
+    let config = shared_config.current();
+    let (chat_id, command_id) = (msg.chat.id, msg.id);
+
+    let reply = match cmd {
+        Command::Start { payload } => match deeplink::city_from_payload(&payload) {
+            Some(city) => {
+                if !check_rate_limit(&bot, msg.chat.id, &user_limiter).await? {
+                    return Ok(());
+                }
+                let args = WisArgs {
+                    city,
+                    max_age: None,
+                    days: None,
+                    pin: false,
+                    alt: false,
+                    both_standards: false,
+                };
+                send_city_report(&bot, msg.chat.id, &waqi, &store, &args, &settings, &me).await?
+            }
+            None => {
+                bot.send_message(msg.chat.id, Command::descriptions().to_string())
+                    .await?
+            }
+        },
+        Command::Help => {
+            bot.send_message(msg.chat.id, Command::descriptions().to_string())
+                .await?
+        }
+        Command::Wis { city } => {
+            let usage = "Usage:\n/wis city_name\n/wis city_name@both\n/wis city_name --max-age 1h --days 3 --pin --alt";
+            let args = match parse_wis_args(&city) {
+                Ok(args) if !args.city.is_empty() => args,
+                Ok(_) => {
+                    bot.send_message(msg.chat.id, usage).await?;
+                    return Ok(());
+                }
+                Err(e) => {
+                    bot.send_message(msg.chat.id, format!("{e}\n{usage}"))
+                        .await?;
+                    return Ok(());
+                }
+            };
+            if !check_rate_limit(&bot, msg.chat.id, &user_limiter).await? {
+                return Ok(());
+            }
+            let report =
+                send_city_report(&bot, msg.chat.id, &waqi, &store, &args, &settings, &me).await?;
+            if args.pin {
+                pin_report(&bot, &store, &settings, &report).await?;
+            }
+            report
+        }
+        Command::Nearby { location } => {
+            if location.trim().is_empty() {
+                bot.send_message(
+                    msg.chat.id,
+                    "Usage:\n/nearby lat,lon\n/nearby google_maps_link",
+                )
+                .await?;
+                return Ok(());
+            }
+            let Some((lat, lon)) = parse_coordinates(&location) else {
+                bot.send_message(
+                    msg.chat.id,
+                    "Couldn't find coordinates in that.\n\
+                     Send coordinates like 35.69,51.39 or a Google Maps link containing \
+                     @lat,lon or ?q=lat,lon (short maps.app.goo.gl links have to be opened first).",
+                )
+                .await?;
+                return Ok(());
+            };
+            if !check_rate_limit(&bot, msg.chat.id, &user_limiter).await? {
+                return Ok(());
+            }
+            let target = format!("geo:{lat};{lon}");
+            let result = match get_city_pollution_emoji(&waqi, &target, &settings).await {
+                Ok(text) => text,
+                Err(e) => failure_reply(format!("Couldn't get data near {lat},{lon}"), e),
+            };
+            bot.send_message(msg.chat.id, result).await?
+        }
+        Command::Cleanest { location } => {
+            let Some((lat, lon)) = parse_coordinates(&location) else {
+                bot.send_message(
+                    msg.chat.id,
+                    "Usage:\n/cleanest lat,lon\n/cleanest google_maps_link",
+                )
+                .await?;
+                return Ok(());
+            };
+            if !check_rate_limit(&bot, msg.chat.id, &user_limiter).await? {
+                return Ok(());
+            }
+            let radius = config.cleanest_radius_km;
+            let result = match waqi.stations_around(lat, lon, radius).await {
+                Ok(stations) => render_cleanest(&stations, (lat, lon), radius),
+                Err(e) => failure_reply(format!("Couldn't get stations near {lat},{lon}"), e),
+            };
+            bot.send_message(msg.chat.id, result).await?
+        }
+        Command::Verbosity { level } => {
+            if level.trim().is_empty() {
+                bot.send_message(
+                    msg.chat.id,
+                    format!(
+                        "Current verbosity: {}\nUsage:\n/verbosity brief|normal|detailed",
+                        settings.verbosity
+                    ),
+                )
+                .await?;
+                return Ok(());
+            }
+            let reply = match level.parse::<Verbosity>() {
+                Ok(verbosity) => {
+                    store
+                        .update_settings(msg.chat.id, |s| s.verbosity = verbosity)
+                        .await;
+                    format!("Verbosity set to {verbosity}.")
+                }
+                Err(e) => format!("{e}\nUsage:\n/verbosity brief|normal|detailed"),
+            };
+            bot.send_message(msg.chat.id, reply).await?
+        }
+        Command::ValueMode { mode } => {
+            let usage = "Usage:\n/valuemode aqi|concentration";
+            if mode.trim().is_empty() {
+                bot.send_message(
+                    msg.chat.id,
+                    format!("Current value mode: {}\n{usage}", settings.value_mode),
+                )
+                .await?;
+                return Ok(());
+            }
+            let reply = match mode.parse::<ValueMode>() {
+                Ok(value_mode) => {
+                    store
+                        .update_settings(msg.chat.id, |s| s.value_mode = value_mode)
+                        .await;
+                    format!("Value mode set to {value_mode}.")
+                }
+                Err(e) => format!("{e}\n{usage}"),
+            };
+            bot.send_message(msg.chat.id, reply).await?
+        }
+        Command::Heatmap { city } => {
+            if city.trim().is_empty() {
+                bot.send_message(msg.chat.id, "Usage:\n/heatmap city_name")
+                    .await?;
+                return Ok(());
+            }
+            if !check_rate_limit(&bot, msg.chat.id, &user_limiter).await? {
+                return Ok(());
+            }
+            // Stringify the error so nothing non-Send is held across the awaits below.
+            match get_city_heatmap(&waqi, &city)
+                .await
+                .map_err(|e| e.to_string())
+            {
+                Ok(Some(png)) => {
+                    bot.send_photo(msg.chat.id, InputFile::memory(png).file_name("heatmap.png"))
+                        .await?
+                }
+                Ok(None) => {
+                    bot.send_message(msg.chat.id, format!("No forecast available for {city}."))
+                        .await?
+                }
+                Err(e) => {
+                    bot.send_message(
+                        msg.chat.id,
+                        failure_reply(format!("Couldn't get data for {city}"), e),
+                    )
+                    .await?
+                }
+            }
+        }
+        Command::Card { city } => {
+            if city.trim().is_empty() {
+                bot.send_message(msg.chat.id, "Usage:\n/card city_name")
+                    .await?;
+                return Ok(());
+            }
+            if !check_rate_limit(&bot, msg.chat.id, &user_limiter).await? {
+                return Ok(());
+            }
+            let attribution = format!("@{} · Data: World Air Quality Index Project", me.username());
+            // Stringify the error so nothing non-Send is held across the awaits below.
+            match get_city_card(&waqi, &city, &settings, &attribution)
+                .await
+                .map_err(|e| e.to_string())
+            {
+                Ok(png) => {
+                    bot.send_photo(msg.chat.id, InputFile::memory(png).file_name("card.png"))
+                        .await?
+                }
+                Err(e) => {
+                    bot.send_message(
+                        msg.chat.id,
+                        failure_reply(format!("Couldn't get data for {city}"), e),
+                    )
+                    .await?
+                }
+            }
+        }
+        Command::Avg { cities } => {
+            let cities = match parse_avg_cities(&cities) {
+                Ok(cities) => cities,
+                Err(e) => {
+                    bot.send_message(
+                        msg.chat.id,
+                        format!("{e}\nUsage:\n/avg city_name, city_name, ..."),
+                    )
+                    .await?;
+                    return Ok(());
+                }
+            };
+            if !check_rate_limit(&bot, msg.chat.id, &user_limiter).await? {
+                return Ok(());
+            }
+            let permits = Arc::new(Semaphore::new(config.scheduler_concurrency));
+            let targets = cities
+                .iter()
+                .map(|city| (city.to_lowercase(), city.clone()))
+                .collect();
+            let readings = scheduler::fetch_all(&waqi, &permits, targets).await;
+            bot.send_message(msg.chat.id, render_average(&cities, &readings, &settings))
+                .await?
+        }
+        Command::Subscribe { args } => {
+            let Some(mut subscription) = parse_subscription(&args) else {
+                bot.send_message(
+                    msg.chat.id,
+                    "Usage:\n/subscribe city_name hour\n/subscribe geo:lat;lon hour\n/subscribe city_name onchange\nFor example: /subscribe tehran 8",
+                )
+                .await?;
+                return Ok(());
+            };
+            let subscriptions = store.subscriptions(msg.chat.id).await;
+            let is_new = !subscriptions
+                .iter()
+                .any(|s| s.target.key() == subscription.target.key());
+            if is_new && subscriptions.len() >= MAX_SUBSCRIPTIONS {
+                bot.send_message(
+                    msg.chat.id,
+                    format!("You can subscribe to at most {MAX_SUBSCRIPTIONS} cities."),
+                )
+                .await?;
+                return Ok(());
+            }
+            if !check_rate_limit(&bot, msg.chat.id, &user_limiter).await? {
+                return Ok(());
+            }
+            // A city that doesn't resolve now would fail silently every day.
+            match waqi.feed(&subscription.target.feed_target()).await {
+                Ok(data) => {
+                    if let Target::Named(_) = subscription.target {
+                        subscription.station = Some(data.idx);
+                        subscription.coords = data.city.coords();
+                    }
+                }
+                Err(e) => {
+                    let reply = match (e, &subscription.target) {
+                        (WaqiError::CityNotFound, Target::Geo(lat, lon)) => {
+                            format!("No station found near {lat},{lon}.")
+                        }
+                        (WaqiError::CityNotFound, Target::Named(city)) => {
+                            let closest = waqi
+                                .search(city)
+                                .await
+                                .ok()
+                                .and_then(|results| results.into_iter().next());
+                            match closest {
+                                Some(closest) => format!(
+                                    "No station found for {}. The closest match is {}, to subscribe to it:\n/subscribe @{} {}",
+                                    city,
+                                    closest.station.name,
+                                    closest.uid,
+                                    subscription.schedule_arg()
+                                ),
+                                None => format!("No station found for {city}."),
+                            }
+                        }
+                        (e @ WaqiError::Demo, _) => e.to_string(),
+                        (e, target) => failure_reply(
+                            format!("Couldn't check {target} right now, please try again later."),
+                            e,
+                        ),
+                    };
+                    bot.send_message(msg.chat.id, reply).await?;
+                    return Ok(());
+                }
+            }
+            let mut reply = match subscription.mode {
+                AlertMode::Daily => format!(
+                    "You'll get a digest of {} {}.",
+                    subscription.target,
+                    subscription.schedule(settings.time_format)
+                ),
+                AlertMode::BandChange => format!(
+                    "You'll be alerted whenever the air quality band of {} changes.",
+                    subscription.target
+                ),
+                AlertMode::ForecastWorse => format!(
+                    "You'll be alerted whenever a coming day in {} is forecast worse than now.",
+                    subscription.target
+                ),
+            };
+            let target = subscription.target.clone();
+            if let Some(replaced) = store.add_subscription(msg.chat.id, subscription).await {
+                if replaced.target.key() != target.key() {
+                    reply.push_str(&format!(
+                        "\n{} is the same station as {target}, so it replaces that subscription.",
+                        replaced.target
+                    ));
+                }
+            }
+            bot.send_message(msg.chat.id, reply).await?
+        }
+        Command::Subscriptions => {
+            let subscriptions = store.subscriptions(msg.chat.id).await;
+            let reply = if subscriptions.is_empty() {
+                "You have no subscriptions.\nUsage:\n/subscribe city_name hour".to_owned()
+            } else {
+                let mut reply = String::from("Your subscriptions:\n");
+                for (i, subscription) in subscriptions.iter().enumerate() {
+                    reply.push_str(&format!(
+                        "{}. {} {}\n",
+                        i + 1,
+                        subscription.target,
+                        subscription.schedule(settings.time_format)
+                    ));
+                }
+                reply.push_str("\nStop one with /unsub and its number, e.g. /unsub 1.\n");
+                if let Some(until) = settings.muted_until {
+                    if settings.is_muted(Utc::now()) {
+                        reply.push_str(&format!(
+                            "\nMuted until {}.",
+                            format_local(until, &settings)
+                        ));
+                    }
+                }
+                reply
+            };
+            bot.send_message(msg.chat.id, reply).await?
+        }
+        Command::Unsub { index } => {
+            let usage = "Usage:\n/unsub number (as listed by /subscriptions)";
+            let reply = match index.trim().parse::<usize>() {
+                Ok(number) if number >= 1 => {
+                    match store.remove_subscription_at(msg.chat.id, number - 1).await {
+                        Some(removed) => format!(
+                            "Unsubscribed from {} {}.",
+                            removed.target,
+                            removed.schedule(settings.time_format)
+                        ),
+                        None => {
+                            let count = store.subscriptions(msg.chat.id).await.len();
+                            match count {
+                                0 => "You have no subscriptions.".to_owned(),
+                                count => format!(
+                                    "There's no subscription {number}, you have {count}.\n\
+                                     See them with /subscriptions."
+                                ),
+                            }
+                        }
+                    }
+                }
+                _ => usage.to_owned(),
+            };
+            bot.send_message(msg.chat.id, reply).await?
+        }
+        Command::Unsubscribe { city } => {
+            if city.trim().is_empty() {
+                bot.send_message(msg.chat.id, "Usage:\n/unsubscribe city_name")
+                    .await?;
+                return Ok(());
+            }
+            let reply = if store
+                .remove_subscription(msg.chat.id, &parse_target(&city))
+                .await
+            {
+                format!("Unsubscribed from {}.", city.trim())
+            } else {
+                format!("You're not subscribed to {}.", city.trim())
+            };
+            bot.send_message(msg.chat.id, reply).await?
+        }
+        Command::TestDigest => {
+            let subscriptions = store.subscriptions(msg.chat.id).await;
+            if subscriptions.is_empty() {
+                bot.send_message(
+                    msg.chat.id,
+                    "You have no subscriptions.\nUsage:\n/subscribe city_name hour",
+                )
+                .await?;
+                return Ok(());
+            }
+            if !check_rate_limit(&bot, msg.chat.id, &user_limiter).await? {
+                return Ok(());
+            }
+            // Leave time to send whatever was fetched before the command times out.
+            let budget = Duration::from_secs(config.command_timeout_secs) * 4 / 5;
+            let deadline = tokio::time::Instant::now() + budget;
+            for text in render_digest(&waqi, &subscriptions, &settings, deadline).await {
+                bot.send_message(msg.chat.id, text).await?;
+            }
+            return Ok(());
+        }
+        Command::Focus { pollutant } => {
+            let pollutant = pollutant.trim().to_lowercase();
+            let parsed = parse_pollutant(&pollutant);
+            let usage = format!(
+                "Usage:\n/focus pollutant\n/focus off\nSupported pollutants: {}",
+                SUPPORTED_POLLUTANTS.join(", ")
+            );
+            let reply = if pollutant.is_empty() {
+                let current = settings.focus_pollutant.as_deref().unwrap_or("off");
+                format!("Current focus: {current}\n{usage}")
+            } else if pollutant == "off" {
+                store
+                    .update_settings(msg.chat.id, |s| s.focus_pollutant = None)
+                    .await;
+                "Focus cleared, the dominant pollutant will be shown.".to_owned()
+            } else {
+                match parsed {
+                    Ok(name) => {
+                        store
+                            .update_settings(msg.chat.id, |s| {
+                                s.focus_pollutant = Some(name.to_owned())
+                            })
+                            .await;
+                        format!(
+                            "Focus set to {name}. Stations that don't report it will show the dominant pollutant."
+                        )
+                    }
+                    Err(Some(suggestion)) => format!(
+                        "Unknown pollutant: {pollutant}. Did you mean {suggestion}?\n/focus {suggestion}"
+                    ),
+                    Err(None) => format!("Unknown pollutant: {pollutant}\n{usage}"),
+                }
+            };
+            bot.send_message(msg.chat.id, reply).await?
+        }
+        Command::WisForecast { toggle } => {
+            let reply = match parse_toggle(&toggle) {
+                Some(enabled) => {
+                    store
+                        .update_settings(msg.chat.id, |s| s.include_forecast_in_wis = enabled)
+                        .await;
+                    if enabled {
+                        "/wis will include the forecast."
+                    } else {
+                        "/wis will only show the current reading, use /forecast for the forecast."
+                    }
+                    .to_owned()
+                }
+                None => format!(
+                    "Currently {}.\nUsage:\n/wisforecast on|off",
+                    if settings.include_forecast_in_wis {
+                        "on"
+                    } else {
+                        "off"
+                    }
+                ),
+            };
+            bot.send_message(msg.chat.id, reply).await?
+        }
+        Command::BandMarkers { toggle } => {
+            let reply = match parse_toggle(&toggle) {
+                Some(enabled) => {
+                    store
+                        .update_settings(msg.chat.id, |s| s.band_markers = enabled)
+                        .await;
+                    if enabled {
+                        "Readings in the upper half of their band will be marked with 🔸."
+                    } else {
+                        "Band markers hidden."
+                    }
+                    .to_owned()
+                }
+                None => format!(
+                    "Currently {}.\nUsage:\n/bandmarkers on|off",
+                    if settings.band_markers { "on" } else { "off" }
+                ),
+            };
+            bot.send_message(msg.chat.id, reply).await?
+        }
+        Command::Ladder { toggle } => {
+            let reply = match parse_toggle(&toggle) {
+                Some(enabled) => {
+                    store
+                        .update_settings(msg.chat.id, |s| s.show_ladder = enabled)
+                        .await;
+                    if enabled {
+                        "Reports will start with the band ladder."
+                    } else {
+                        "Band ladder hidden."
+                    }
+                    .to_owned()
+                }
+                None => format!(
+                    "Currently {}.\nUsage:\n/ladder on|off",
+                    if settings.show_ladder { "on" } else { "off" }
+                ),
+            };
+            bot.send_message(msg.chat.id, reply).await?
+        }
+        Command::Ascii { toggle } => {
+            let reply = match parse_toggle(&toggle) {
+                Some(enabled) => {
+                    store
+                        .update_settings(msg.chat.id, |s| s.ascii = enabled)
+                        .await;
+                    if enabled {
+                        "Reports will be written without emoji, e.g. [GOOD] and |###.......|."
+                    } else {
+                        "Reports will use emoji again."
+                    }
+                    .to_owned()
+                }
+                None => format!(
+                    "Currently {}.\nUsage:\n/ascii on|off",
+                    if settings.ascii { "on" } else { "off" }
+                ),
+            };
+            bot.send_message(msg.chat.id, reply).await?
+        }
+        Command::Cigarettes { toggle } => {
+            let reply = match parse_toggle(&toggle) {
+                Some(enabled) => {
+                    store
+                        .update_settings(msg.chat.id, |s| s.show_cigarettes = enabled)
+                        .await;
+                    if enabled {
+                        "Readings will include the cigarettes-per-day equivalent when PM2.5 is reported."
+                    } else {
+                        "Cigarettes-per-day equivalent hidden."
+                    }
+                    .to_owned()
+                }
+                None => format!(
+                    "Currently {}.\nUsage:\n/cigarettes on|off",
+                    if settings.show_cigarettes {
+                        "on"
+                    } else {
+                        "off"
+                    }
+                ),
+            };
+            bot.send_message(msg.chat.id, reply).await?
+        }
+        Command::Conditions { toggle } => {
+            let reply = match parse_toggle(&toggle) {
+                Some(enabled) => {
+                    store
+                        .update_settings(msg.chat.id, |s| s.show_conditions = enabled)
+                        .await;
+                    if enabled {
+                        "Detailed readings will include the weather conditions."
+                    } else {
+                        "Weather conditions hidden."
+                    }
+                    .to_owned()
+                }
+                None => format!(
+                    "Currently {}.\nUsage:\n/conditions on|off",
+                    if settings.show_conditions {
+                        "on"
+                    } else {
+                        "off"
+                    }
+                ),
+            };
+            bot.send_message(msg.chat.id, reply).await?
+        }
+        Command::Bands { cutoffs } => {
+            let cutoffs = cutoffs.trim();
+            let usage = "Usage:\n/bands 40 80 120 160 250\n/bands off\n\
+                         The numbers are the highest AQI of Good, Moderate, Unhealthy for \
+                         Sensitive Groups, Unhealthy and Very Unhealthy.";
+            let reply = if cutoffs.is_empty() {
+                let current = settings.band_cutoffs.map_or("standard".to_owned(), |c| {
+                    c.map(|cutoff| cutoff.to_string()).join(" ")
+                });
+                format!("Current bands: {current}\n{usage}")
+            } else if cutoffs.eq_ignore_ascii_case("off") {
+                store
+                    .update_settings(msg.chat.id, |s| s.band_cutoffs = None)
+                    .await;
+                "Back to the standard bands.".to_owned()
+            } else {
+                match parse_band_cutoffs(cutoffs) {
+                    Ok(parsed) => {
+                        store
+                            .update_settings(msg.chat.id, |s| s.band_cutoffs = Some(parsed))
+                            .await;
+                        let parsed = parsed.map(|cutoff| cutoff.to_string()).join(" ");
+                        format!("Bands set to {parsed}.")
+                    }
+                    Err(e) => format!("{e}\n{usage}"),
+                }
+            };
+            bot.send_message(msg.chat.id, reply).await?
+        }
+        Command::Preview { city } => {
+            if city.trim().is_empty() {
+                bot.send_message(msg.chat.id, "Usage:\n/preview city_name")
+                    .await?;
+                return Ok(());
+            }
+            if !check_rate_limit(&bot, msg.chat.id, &user_limiter).await? {
+                return Ok(());
+            }
+            let mut previews = match waqi
+                .feed(&city)
+                .await
+                .map_err(Into::into)
+                .and_then(|data| render_previews(&data, &settings))
+                .map_err(|e| e.to_string())
+            {
+                Ok(previews) => previews,
+                Err(e) => vec![failure_reply(format!("Couldn't get data for {city}"), e)],
+            };
+            // Each preview on its own, a detailed report alone can come close to the message limit.
+            let last = previews.pop().unwrap_or_default();
+            for preview in previews {
+                bot.send_message(msg.chat.id, preview).await?;
+            }
+            bot.send_message(msg.chat.id, last).await?
+        }
+        Command::Sources { city } => {
+            if city.trim().is_empty() {
+                bot.send_message(msg.chat.id, "Usage:\n/sources city_name")
+                    .await?;
+                return Ok(());
+            }
+            if !check_rate_limit(&bot, msg.chat.id, &user_limiter).await? {
+                return Ok(());
+            }
+            let reply = match waqi.feed(&city).await {
+                Ok(data) if data.attributions.is_empty() => {
+                    format!("{} doesn't name its sources.", data.city.name)
+                }
+                Ok(data) => format!("{}\n{}", data.city.name, render_sources(&data)),
+                Err(e) => failure_reply(format!("Couldn't get data for {city}"), e),
+            };
+            bot.send_message(msg.chat.id, reply)
+                .link_preview_options(no_link_preview())
+                .await?
+        }
+        Command::Coverage { city } => {
+            if city.trim().is_empty() {
+                bot.send_message(msg.chat.id, "Usage:\n/coverage city_name")
+                    .await?;
+                return Ok(());
+            }
+            if !check_rate_limit(&bot, msg.chat.id, &user_limiter).await? {
+                return Ok(());
+            }
+            let reply = match waqi.feed(&city).await {
+                Ok(data) => render_coverage(&data),
+                Err(e) => failure_reply(format!("Couldn't get data for {city}"), e),
+            };
+            bot.send_message(msg.chat.id, reply).await?
+        }
+        Command::Calendar { city } => {
+            if city.trim().is_empty() {
+                bot.send_message(msg.chat.id, "Usage:\n/calendar city_name")
+                    .await?;
+                return Ok(());
+            }
+            if !check_rate_limit(&bot, msg.chat.id, &user_limiter).await? {
+                return Ok(());
+            }
+            let result = waqi
+                .feed(&city)
+                .await
+                .map_err(Into::into)
+                .and_then(|data| render_calendar(&data, &settings))
+                .map_err(|e| e.to_string());
+            match result {
+                Ok(text) => send_html(&bot, msg.chat.id, text).await?,
+                Err(e) => {
+                    bot.send_message(
+                        msg.chat.id,
+                        failure_reply(format!("Couldn't get data for {city}"), e),
+                    )
+                    .await?
+                }
+            }
+        }
+        Command::Plan { city } => {
+            if city.trim().is_empty() {
+                bot.send_message(msg.chat.id, "Usage:\n/plan city_name")
+                    .await?;
+                return Ok(());
+            }
+            if !check_rate_limit(&bot, msg.chat.id, &user_limiter).await? {
+                return Ok(());
+            }
+            let result = match waqi
+                .feed(&city)
+                .await
+                .map_err(Into::into)
+                .and_then(|data| render_plan(&data, &settings))
+            {
+                Ok(text) => text,
+                Err(e) => failure_reply(format!("Couldn't get data for {city}"), e),
+            };
+            bot.send_message(msg.chat.id, result).await?
+        }
+        Command::Standards { city } => {
+            if city.trim().is_empty() {
+                bot.send_message(msg.chat.id, "Usage:\n/standards city_name")
+                    .await?;
+                return Ok(());
+            }
+            if !check_rate_limit(&bot, msg.chat.id, &user_limiter).await? {
+                return Ok(());
+            }
+            let result = match waqi
+                .feed(&city)
+                .await
+                .map_err(Into::into)
+                .and_then(|data| render_standards(&data))
+            {
+                Ok(text) => text,
+                Err(e) => failure_reply(format!("Couldn't get data for {city}"), e),
+            };
+            bot.send_message(msg.chat.id, result).await?
+        }
+        Command::Forecast { city } => {
+            if city.trim().is_empty() {
+                bot.send_message(msg.chat.id, "Usage:\n/forecast city_name")
+                    .await?;
+                return Ok(());
+            }
+            if !check_rate_limit(&bot, msg.chat.id, &user_limiter).await? {
+                return Ok(());
+            }
+            let result = match waqi
+                .feed(&city)
+                .await
+                .map_err(Into::into)
+                .and_then(|data| render_forecast(&data, &settings))
+            {
+                Ok(text) => text,
+                Err(e) => failure_reply(format!("Couldn't get data for {city}"), e),
+            };
+            bot.send_message(msg.chat.id, result).await?
+        }
+        Command::VsAverage { city } => {
+            let city = city.trim();
+            if city.is_empty() {
+                bot.send_message(msg.chat.id, "Usage:\n/vsaverage city_name")
+                    .await?;
+                return Ok(());
+            }
+            if !check_rate_limit(&bot, msg.chat.id, &user_limiter).await? {
+                return Ok(());
+            }
+            let result = match waqi.feed(city).await.map_err(|e| e.to_string()) {
+                Ok(data) => match sample_of(&data) {
+                    Some(today) => {
+                        let history = store.history(city).await;
+                        store.record_sample(city, today.clone()).await;
+                        store.record_forecast(city, forecast_samples(&data)).await;
+                        render_vs_average(&data.city.name, &today, &history)
+                    }
+                    None => format!("Couldn't tell which day the reading for {city} is from."),
+                },
+                Err(e) => failure_reply(format!("Couldn't get data for {city}"), e),
+            };
+            bot.send_message(msg.chat.id, result).await?
+        }
+        Command::Past { args } => {
+            let usage = "Usage:\n/past city_name 2026-01-31\n/past city_name yesterday";
+            let Some((city, date)) = parse_past(&args, Utc::now().date_naive()) else {
+                bot.send_message(msg.chat.id, usage).await?;
+                return Ok(());
+            };
+            if !check_rate_limit(&bot, msg.chat.id, &user_limiter).await? {
+                return Ok(());
+            }
+            let result = match waqi.history(&city, date).await {
+                Ok(day) => render_past(&city, date, &day),
+                Err(e @ (WaqiError::HistoryUnavailable | WaqiError::Demo)) => {
+                    format!("{e} ({city}, {date}).")
+                }
+                Err(e) => failure_reply(format!("Couldn't get data for {city}"), e),
+            };
+            bot.send_message(msg.chat.id, result).await?
+        }
+        Command::Accuracy { city } => {
+            let city = city.trim();
+            if city.is_empty() {
+                bot.send_message(msg.chat.id, "Usage:\n/accuracy city_name")
+                    .await?;
+                return Ok(());
+            }
+            let forecasts = store.forecasts(city).await;
+            let history = store.history(city).await;
+            bot.send_message(msg.chat.id, render_accuracy(city, &forecasts, &history))
+                .await?
+        }
+        Command::Random => {
+            if !check_rate_limit(&bot, msg.chat.id, &user_limiter).await? {
+                return Ok(());
+            }
+            let mut reply = None;
+            // A pick whose station is down just moves on to another one.
+            for _ in 0..RANDOM_ATTEMPTS {
+                let city = WORLD_CITIES[random_index(WORLD_CITIES.len())];
+                match get_city_pollution_emoji(&waqi, city, &settings)
+                    .await
+                    .map_err(|e| e.to_string())
+                {
+                    Ok(text) => {
+                        reply = Some(format!("🎲 {city}\n\n{text}"));
+                        break;
+                    }
+                    Err(e) => tracing::warn!("{e}"),
+                }
+            }
+            let reply = reply.unwrap_or_else(|| {
+                "Couldn't get data for a random city, please try again.".to_owned()
+            });
+            bot.send_message(msg.chat.id, reply).await?
+        }
+        Command::World => {
+            if !check_rate_limit(&bot, msg.chat.id, &user_limiter).await? {
+                return Ok(());
+            }
+            let readings = world_readings(&waqi, config.scheduler_concurrency).await;
+            let (text, keyboard) = render_world(&readings, &settings, 0);
+            let request = bot.send_message(msg.chat.id, text);
+            match keyboard {
+                Some(keyboard) => request.reply_markup(keyboard).await?,
+                None => request.await?,
+            }
+        }
+        Command::Pollutants { city } => {
+            if city.trim().is_empty() {
+                bot.send_message(msg.chat.id, "Usage:\n/pollutants city_name")
+                    .await?;
+                return Ok(());
+            }
+            if !check_rate_limit(&bot, msg.chat.id, &user_limiter).await? {
+                return Ok(());
+            }
+            match waqi.feed(&city).await.map_err(|e| e.to_string()) {
+                Ok(data) => {
+                    send_html(&bot, msg.chat.id, render_pollutants(&data, &settings)).await?
+                }
+                Err(e) => {
+                    bot.send_message(
+                        msg.chat.id,
+                        failure_reply(format!("Couldn't get data for {city}"), e),
+                    )
+                    .await?
+                }
+            }
+        }
+        Command::Explain { city } => {
+            if city.trim().is_empty() {
+                bot.send_message(msg.chat.id, "Usage:\n/explain city_name")
+                    .await?;
+                return Ok(());
+            }
+            if !check_rate_limit(&bot, msg.chat.id, &user_limiter).await? {
+                return Ok(());
+            }
+            let result = match waqi.feed(&city).await.map_err(|e| e.to_string()) {
+                Ok(data) => render_explanation(&data, &settings),
+                Err(e) => failure_reply(format!("Couldn't get data for {city}"), e),
+            };
+            bot.send_message(msg.chat.id, result).await?
+        }
+        Command::DigestLayout { layout } => {
+            let usage = "Usage:\n/digestlayout combined|separate";
+            if layout.trim().is_empty() {
+                bot.send_message(
+                    msg.chat.id,
+                    format!("Current digest layout: {}\n{usage}", settings.digest_layout),
+                )
+                .await?;
+                return Ok(());
+            }
+            let reply = match layout.parse::<DigestLayout>() {
+                Ok(digest_layout) => {
+                    store
+                        .update_settings(msg.chat.id, |s| s.digest_layout = digest_layout)
+                        .await;
+                    format!("Digest layout set to {digest_layout}.")
+                }
+                Err(e) => format!("{e}\n{usage}"),
+            };
+            bot.send_message(msg.chat.id, reply).await?
+        }
+        Command::Standard { standard } => {
+            let usage = "Usage:\n/standard us|eu|auto";
+            let reply = match standard.trim() {
+                "" => {
+                    let current = match (settings.standard, settings.standard_explicit) {
+                        (None, _) => "not chosen yet, your next /wis picks it".to_owned(),
+                        (Some(standard), true) => standard.to_string(),
+                        (Some(standard), false) => format!("{standard}, from your first /wis"),
+                    };
+                    format!("Current standard: {current}\n{usage}")
+                }
+                "auto" => {
+                    store
+                        .update_settings(msg.chat.id, |s| {
+                            s.standard = None;
+                            s.standard_explicit = false;
+                        })
+                        .await;
+                    "Your next /wis will pick the standard of the station's region.".to_owned()
+                }
+                standard => match standard.parse::<Standard>() {
+                    Ok(standard) => {
+                        store
+                            .update_settings(msg.chat.id, |s| {
+                                s.standard = Some(standard);
+                                s.standard_explicit = true;
+                            })
+                            .await;
+                        format!("Reports are now graded on the {standard} scale.")
+                    }
+                    Err(e) => format!("{e}\n{usage}"),
+                },
+            };
+            bot.send_message(msg.chat.id, reply).await?
+        }
+        Command::TimeFormat { format } => {
+            let usage = "Usage:\n/timeformat 12h|24h";
+            if format.trim().is_empty() {
+                bot.send_message(
+                    msg.chat.id,
+                    format!("Current time format: {}\n{usage}", settings.time_format),
+                )
+                .await?;
+                return Ok(());
+            }
+            let reply = match format.parse::<TimeFormat>() {
+                Ok(time_format) => {
+                    store
+                        .update_settings(msg.chat.id, |s| s.time_format = time_format)
+                        .await;
+                    format!("Times are now shown like {}.", time_format.hour(20))
+                }
+                Err(e) => format!("{e}\n{usage}"),
+            };
+            bot.send_message(msg.chat.id, reply).await?
+        }
+        Command::ForecastDays { days } => {
+            let days = days.trim();
+            let usage = "Usage:\n/forecastdays 3\n/forecastdays all";
+            let reply = if days.is_empty() {
+                let current = settings
+                    .forecast_days
+                    .map_or("all".to_owned(), |days| days.to_string());
+                format!("Currently showing forecast days: {current}\n{usage}")
+            } else if days.eq_ignore_ascii_case("all") {
+                store
+                    .update_settings(msg.chat.id, |s| s.forecast_days = None)
+                    .await;
+                "Showing every forecast day.".to_owned()
+            } else {
+                match parse_forecast_days(days) {
+                    Ok(days) => {
+                        store
+                            .update_settings(msg.chat.id, |s| s.forecast_days = Some(days))
+                            .await;
+                        format!("Showing up to {days} forecast days.")
+                    }
+                    Err(e) => format!("{e}\n{usage}"),
+                }
+            };
+            bot.send_message(msg.chat.id, reply).await?
+        }
+        Command::Timezone { timezone } => {
+            let timezone = timezone.trim();
+            let usage = "Usage:\n/timezone Europe/Berlin\n/timezone off";
+            let reply = if timezone.is_empty() {
+                let current = settings
+                    .timezone
+                    .map_or("server time".to_owned(), |tz| tz.name().to_owned());
+                format!("Digest hours are currently in: {current}\n{usage}")
+            } else if timezone.eq_ignore_ascii_case("off") {
+                store
+                    .update_settings(msg.chat.id, |s| s.timezone = None)
+                    .await;
+                "Digest hours are now in server time.".to_owned()
+            } else {
+                match timezone.parse::<Tz>() {
+                    Ok(tz) => {
+                        store
+                            .update_settings(msg.chat.id, |s| s.timezone = Some(tz))
+                            .await;
+                        format!("Digest hours are now in {}.", tz.name())
+                    }
+                    Err(_) => format!("Unknown time zone: {timezone}\n{usage}"),
+                }
+            };
+            bot.send_message(msg.chat.id, reply).await?
+        }
+        Command::StaleAfter { duration } => {
+            let duration = duration.trim();
+            let usage = "Usage:\n/staleafter 2h\n/staleafter off";
+            let reply = if duration.is_empty() {
+                let current = match settings.stale_after_secs {
+                    0 => "off".to_owned(),
+                    secs => humantime::format_duration(Duration::from_secs(secs)).to_string(),
+                };
+                format!("Currently warning after: {current}\n{usage}")
+            } else if duration.eq_ignore_ascii_case("off") {
+                store
+                    .update_settings(msg.chat.id, |s| s.stale_after_secs = 0)
+                    .await;
+                "Stale data warnings turned off.".to_owned()
+            } else {
+                match humantime::parse_duration(duration) {
+                    Ok(threshold) if threshold.as_secs() > 0 => {
+                        store
+                            .update_settings(msg.chat.id, |s| {
+                                s.stale_after_secs = threshold.as_secs()
+                            })
+                            .await;
+                        format!("You'll be warned about readings older than {duration}.")
+                    }
+                    Ok(_) => format!("The duration must be at least a second.\n{usage}"),
+                    Err(e) => format!("Invalid duration ({duration}): {e}\n{usage}"),
+                }
+            };
+            bot.send_message(msg.chat.id, reply).await?
+        }
+        Command::Mute { duration } => {
+            let duration = duration.trim();
+            let usage = "Usage:\n/mute 7d";
+            if duration.is_empty() {
+                bot.send_message(msg.chat.id, usage).await?;
+                return Ok(());
+            }
+            let until = humantime::parse_duration(duration)
+                .map_err(|e| format!("Invalid duration ({duration}): {e}\n{usage}"))
+                .and_then(|duration| {
+                    chrono::Duration::from_std(duration)
+                        .ok()
+                        .and_then(|duration| Utc::now().checked_add_signed(duration))
+                        .ok_or_else(|| format!("That's too long.\n{usage}"))
+                });
+            let reply = match until {
+                Ok(until) => {
+                    store
+                        .update_settings(msg.chat.id, |s| s.muted_until = Some(until))
+                        .await;
+                    format!(
+                        "Scheduled messages muted until {}.\nUse /unmute to resume earlier.",
+                        format_local(until, &settings)
+                    )
+                }
+                Err(e) => e,
+            };
+            bot.send_message(msg.chat.id, reply).await?
+        }
+        Command::Unmute => {
+            let reply = if settings.is_muted(Utc::now()) {
+                store
+                    .update_settings(msg.chat.id, |s| s.muted_until = None)
+                    .await;
+                "Scheduled messages resumed."
+            } else {
+                "Scheduled messages aren't muted."
+            };
+            bot.send_message(msg.chat.id, reply).await?
+        }
+        Command::Limits => {
+            let cooldown = Duration::from_secs(config.rate_limit.heavy_command_cooldown_secs);
+            let reply = render_limits(msg.chat.id, &user_limiter, cooldown, &waqi);
+            bot.send_message(msg.chat.id, reply).await?
+        }
+        Command::Tips => bot.send_message(msg.chat.id, LOOKUP_TIPS).await?,
+        Command::EmojiTest => bot.send_message(msg.chat.id, render_emoji_test()).await?,
+        Command::ExportSettings => {
+            let export = store.export(msg.chat.id).await;
+            let contents = match serde_json::to_vec_pretty(&export) {
+                Ok(contents) => contents,
+                Err(e) => {
+                    tracing::error!("Failed to serialize settings of {}: {e}", msg.chat.id);
+                    bot.send_message(msg.chat.id, "Couldn't export your settings.")
+                        .await?;
+                    return Ok(());
+                }
+            };
+            bot.send_document(
+                msg.chat.id,
+                InputFile::memory(contents).file_name("wis-settings.json"),
+            )
+            .caption("Your settings and subscriptions. Reply to this file with /importsettings to restore them.")
+            .await?
+        }
+        Command::ImportSettings => {
+            let Some(document) = msg.reply_to_message().and_then(|reply| reply.document()) else {
+                bot.send_message(
+                    msg.chat.id,
+                    "Reply to a file made by /exportsettings with /importsettings to restore it.",
+                )
+                .await?;
+                return Ok(());
+            };
+            if document.file.size > MAX_IMPORT_BYTES {
+                bot.send_message(
+                    msg.chat.id,
+                    "That file is too large to be a settings export.",
+                )
+                .await?;
+                return Ok(());
+            }
+            let file = bot.get_file(document.file.id.clone()).await?;
+            let mut contents = Vec::new();
+            if let Err(e) = bot.download_file(&file.path, &mut contents).await {
+                tracing::warn!("Failed to download settings import: {e}");
+                bot.send_message(
+                    msg.chat.id,
+                    "Couldn't download that file, please try again.",
+                )
+                .await?;
+                return Ok(());
+            }
+            let reply = match serde_json::from_slice::<UserExport>(&contents)
+                .map_err(|e| e.to_string())
+                .and_then(|export| validate_import(export, &settings))
+            {
+                Ok(export) => {
+                    let reply = format!(
+                        "Restored your settings and {} subscription(s).",
+                        export.subscriptions.len()
+                    );
+                    store.import(msg.chat.id, export).await;
+                    reply
+                }
+                Err(e) => format!("That isn't a valid settings export: {e}"),
+            };
+            bot.send_message(msg.chat.id, reply).await?
+        }
+        Command::Settings => {
+            bot.send_message(msg.chat.id, settings_menu::render(&settings))
+                .reply_markup(settings_menu::keyboard(&settings))
+                .await?
+        }
+        Command::Version => {
+            let reply = version::render(config.check_updates).await;
+            bot.send_message(msg.chat.id, reply).await?
+        }
+        Command::Feedback { text } => {
+            let text = text.trim();
+            if text.is_empty() {
+                bot.send_message(msg.chat.id, "Usage:\n/feedback your message")
+                    .await?;
+                return Ok(());
+            }
+            if config.admin_chat_ids.is_empty() {
+                bot.send_message(msg.chat.id, "Feedback isn't set up on this bot.")
+                    .await?;
+                return Ok(());
+            }
+            if !user_limiter.feedback.try_acquire(msg.chat.id) {
+                bot.send_message(
+                    msg.chat.id,
+                    "You've just sent feedback, please wait a minute before sending more.",
+                )
+                .await?;
+                return Ok(());
+            }
+
+            let sender = msg
+                .from
+                .as_ref()
+                .map_or("someone".to_owned(), |user| user.full_name());
+            let forwarded = format!("📝 Feedback from {sender} (chat {}):\n{text}", msg.chat.id);
+            let mut delivered = false;
+            for admin in &config.admin_chat_ids {
+                match bot.send_message(ChatId(*admin), forwarded.as_str()).await {
+                    Ok(_) => delivered = true,
+                    Err(e) => tracing::warn!("Failed to forward feedback to {admin}: {e}"),
+                }
+            }
+
+            let reply = if delivered {
+                "Thanks, your feedback has been sent."
+            } else {
+                "Sorry, your feedback couldn't be delivered. Please try again later."
+            };
+            bot.send_message(msg.chat.id, reply).await?
+        }
+        Command::Stats => {
+            if !config.is_admin(msg.chat.id.0) {
+                bot.send_message(msg.chat.id, "This command is only available to admins.")
+                    .await?;
+                return Ok(());
+            }
+            bot.send_message(msg.chat.id, render_stats(&waqi)).await?
+        }
+        Command::Debug => {
+            if !config.is_admin(msg.chat.id.0) {
+                bot.send_message(msg.chat.id, "This command is only available to admins.")
+                    .await?;
+                return Ok(());
+            }
+            bot.send_message(msg.chat.id, render_debug(&config)).await?
+        }
+        Command::Probe { target } => {
+            if !config.is_admin(msg.chat.id.0) {
+                bot.send_message(msg.chat.id, "This command is only available to admins.")
+                    .await?;
+                return Ok(());
+            }
+            let target = target.trim();
+            if target.is_empty() {
+                bot.send_message(
+                    msg.chat.id,
+                    "Usage:\n/probe city_name\n/probe @station_uid\n/probe geo:lat;lon",
+                )
+                .await?;
+                return Ok(());
+            }
+            let probe = waqi.probe(target).await;
+            bot.send_message(msg.chat.id, render_probe(target, &probe))
+                .link_preview_options(no_link_preview())
+                .await?
+        }
+        Command::Broadcast { text } => {
+            if !config.is_admin(msg.chat.id.0) {
+                bot.send_message(msg.chat.id, "This command is only available to admins.")
+                    .await?;
+                return Ok(());
+            }
+            let text = text.trim();
+            if text.is_empty() {
+                bot.send_message(msg.chat.id, "Usage:\n/broadcast message")
+                    .await?;
+                return Ok(());
+            }
+            let chats = store.known_chats().await;
+            bot.send_message(
+                msg.chat.id,
+                format!(
+                    "Broadcasting to {} chats, I'll report back when done.",
+                    chats.len()
+                ),
+            )
+            .await?;
+            broadcast::spawn(Bot::clone(&bot), msg.chat.id, chats, text.to_owned());
+            return Ok(());
+        }
+        Command::AlertRegion { args } => {
+            if !config.is_admin(msg.chat.id.0) {
+                bot.send_message(msg.chat.id, "This command is only available to admins.")
+                    .await?;
+                return Ok(());
+            }
+            let Some((lat, lon, text)) = parse_region_alert(&args) else {
+                bot.send_message(
+                    msg.chat.id,
+                    "Usage:\n/alertregion lat1,lon1,lat2,lon2 message\nFor example: /alertregion 35.5,51.1,35.9,51.7 Stay indoors today.",
+                )
+                .await?;
+                return Ok(());
+            };
+            let chats = store.chats_within(lat, lon).await;
+            bot.send_message(
+                msg.chat.id,
+                format!(
+                    "Alerting {} chats subscribed inside the region, I'll report back when done.",
+                    chats.len()
+                ),
+            )
+            .await?;
+            broadcast::spawn(Bot::clone(&bot), msg.chat.id, chats, text);
+            return Ok(());
+        }
+        Command::Maintenance { toggle } => {
+            if !config.is_admin(msg.chat.id.0) {
+                bot.send_message(msg.chat.id, "This command is only available to admins.")
+                    .await?;
+                return Ok(());
+            }
+            let reply = match parse_toggle(&toggle) {
+                Some(enabled) => {
+                    shared_config.set_maintenance(enabled);
+                    tracing::warn!(
+                        "Maintenance mode switched {} by {}",
+                        if enabled { "on" } else { "off" },
+                        msg.chat.id
+                    );
+                    if enabled {
+                        "Maintenance mode on, lookups get the maintenance message until /maintenance off or /reload."
+                    } else {
+                        "Maintenance mode off, lookups are served again."
+                    }
+                    .to_owned()
+                }
+                None => format!(
+                    "Currently {}.\nUsage:\n/maintenance on|off",
+                    if config.maintenance_mode { "on" } else { "off" }
+                ),
+            };
+            bot.send_message(msg.chat.id, reply).await?
+        }
+        Command::Reload => {
+            if !config.is_admin(msg.chat.id.0) {
+                bot.send_message(msg.chat.id, "This command is only available to admins.")
+                    .await?;
+                return Ok(());
+            }
+            let reply = match shared_config.reload() {
+                Ok((old, requested)) => {
+                    let running = shared_config.current();
+                    waqi.apply_config(&running);
+                    user_limiter
+                        .lookups
+                        .set_limit(running.rate_limit.per_user_per_minute);
+                    render_config_changes(&old, &requested)
+                }
+                Err(e) => format!("Failed to reload the configuration: {e}"),
+            };
+            bot.send_message(msg.chat.id, reply).await?
+        }
+    };
+    replies.record(chat_id, command_id, reply.id);
+
+    Ok(())
+}
 
 // --------------------- //
-// BEGIN WAQI Data Model //
+// BEGIN Helper Functions//
 // --------------------- //
 
-#[allow(unused)]
-#[derive(Debug, Deserialize)]
-struct ApiResponse {
-    status: String,
-    data: PollutionData,
+/// The dominant pollutant's band on the US EPA scale next to the EU CAQI one.
+fn render_standards(data: &PollutionData) -> Result<String, Box<dyn std::error::Error>> {
+    let dominant = data.dominentpol.as_str();
+    let sub_index = data
+        .iaqi
+        .get(dominant)
+        .ok_or_else(|| format!("Data for dominant pollutant ({dominant}) not available."))?
+        .v;
+
+    // WAQI's sub-index already is the US AQI, the EU index needs it back as a concentration.
+    let us_aqi = sub_index.round() as u32;
+    let us_level = AirQualityLevel::try_from(us_aqi)
+        .map_err(|e| format!("Failed to determine US AQI band from {dominant}: {e}"))?;
+    let (us_emoji, _) = air_quality_to_emoji(us_level, us_aqi);
+
+    let mut text = format!(
+        "{}\nDominant pollutant: {dominant}\n\n🇺🇸 US EPA: {us_emoji} {us_level} (AQI {us_aqi})\n",
+        data.city.name
+    );
+
+    let eu = dominant_caqi(dominant, sub_index);
+    text.push_str(&caqi_line(dominant, eu.as_ref()));
+    let Some(eu) = eu else {
+        text.push('\n');
+        return Ok(text);
+    };
+    text.push_str("\n\n");
+
+    // CAQI has one band fewer, so both top US bands line up with "Very high".
+    if us_rank(us_level).min(4) == eu.level.rank() {
+        text.push_str("✅ Both scales put this reading in the same band.");
+    } else {
+        text.push_str(&format!(
+            "⚠️ The scales disagree: US EPA says {us_level}, EU CAQI says {}.",
+            eu.level
+        ));
+    }
+
+    Ok(text)
+}
+
+/// The EU CAQI of `dominant` from its WAQI sub-index, which CAQI needs back as a concentration.
+fn dominant_caqi(dominant: &str, sub_index: f64) -> Option<caqi::Caqi> {
+    concentration::ug_m3(dominant, sub_index)
+        .and_then(|concentration| caqi::caqi(dominant, concentration))
+}
+
+/// "🇪🇺 EU CAQI: 💛 Low (CAQI 40)", or that CAQI doesn't cover `dominant`.
+fn caqi_line(dominant: &str, eu: Option<&caqi::Caqi>) -> String {
+    match eu {
+        Some(eu) => {
+            let eu_emoji = ["💚", "💛", "🧡", "❤️", "💜"][eu.level.rank()];
+            format!("🇪🇺 EU CAQI: {eu_emoji} {} (CAQI {})", eu.level, eu.index)
+        }
+        None => format!("🇪🇺 EU CAQI: doesn't cover {dominant}"),
+    }
+}
+
+/// The CAQI line appended to the reports of users on the European standard, if the station
+/// reports its dominant pollutant.
+fn render_caqi(data: &PollutionData) -> Option<String> {
+    let dominant = data.dominentpol.as_str();
+    let sub_index = data.iaqi.get(dominant)?.v;
+    Some(caqi_line(
+        dominant,
+        dominant_caqi(dominant, sub_index).as_ref(),
+    ))
+}
+
+/// Days before today `/vsaverage` averages over.
+const AVERAGE_WINDOW_DAYS: i64 = 7;
+
+/// Fewest earlier days `/vsaverage` needs before it compares.
+const MIN_AVERAGE_SAMPLES: usize = 3;
+
+/// Most recent forecast days `/accuracy` checks.
+const ACCURACY_WINDOW_DAYS: usize = 7;
+
+fn render_vs_average(city: &str, today: &Sample, history: &[Sample]) -> String {
+    let window_start = today.date - chrono::Duration::days(AVERAGE_WINDOW_DAYS);
+    let earlier: Vec<u32> = history
+        .iter()
+        .filter(|s| s.date >= window_start && s.date < today.date)
+        .map(|s| s.aqi)
+        .collect();
+    if earlier.len() < MIN_AVERAGE_SAMPLES {
+        return format!(
+            "Not enough history for {city} yet ({} of {MIN_AVERAGE_SAMPLES} days recorded). \
+             Today's AQI of {} has been saved, check back in a few days.",
+            earlier.len(),
+            today.aqi
+        );
+    }
+
+    let average = earlier.iter().sum::<u32>() as f64 / earlier.len() as f64;
+    let change = (today.aqi as f64 - average) / average.max(1.0) * 100.0;
+    let comparison = if change.abs() < 1.0 {
+        "about the same as".to_owned()
+    } else if change < 0.0 {
+        format!("{:.0}% better than", -change)
+    } else {
+        format!("{change:.0}% worse than")
+    };
+
+    format!(
+        "{city}: today's AQI of {} is {comparison} the {AVERAGE_WINDOW_DAYS}-day average of {average:.0} ({} days recorded).",
+        today.aqi,
+        earlier.len()
+    )
+}
+
+/// Fewest earlier days needed before today is ranked against them.
+const MIN_PERCENTILE_SAMPLES: usize = 10;
+
+/// Where `today` falls among the earlier days recorded for the city, e.g. "among the worst
+/// 10%". Gives local context the absolute bands miss, once there's enough history.
+fn percentile_line(today: &Sample, history: &[Sample]) -> Option<String> {
+    let earlier: Vec<u32> = history
+        .iter()
+        .filter(|s| s.date < today.date)
+        .map(|s| s.aqi)
+        .collect();
+    if earlier.len() < MIN_PERCENTILE_SAMPLES {
+        return None;
+    }
+
+    // Share of earlier days that were cleaner, counting ties as half.
+    let cleaner = earlier.iter().filter(|aqi| **aqi < today.aqi).count() as f64;
+    let ties = earlier.iter().filter(|aqi| **aqi == today.aqi).count() as f64;
+    let percentile = (cleaner + ties / 2.0) / earlier.len() as f64 * 100.0;
+
+    let verdict = match percentile {
+        p if p >= 90.0 => "🔺 Among the worst 10% of days",
+        p if p >= 75.0 => "🔸 Worse than usual compared to the days",
+        p if p > 25.0 => "▫️ A typical day compared to the days",
+        p if p > 10.0 => "🔹 Better than usual compared to the days",
+        _ => "🟢 Among the cleanest 10% of days",
+    };
+    Some(format!(
+        "{verdict} recorded here (worse than {percentile:.0}% of {} days)\n",
+        earlier.len()
+    ))
+}
+
+/// The reading as a history sample, dated by the station's local date.
+fn sample_of(data: &PollutionData) -> Option<Sample> {
+    let date = current_date(data).ok()?;
+    Some(Sample {
+        date,
+        aqi: data.aqi,
+    })
+}
+
+/// The forecast AQI of the station's dominant pollutant for the days after the reading.
+fn forecast_samples(data: &PollutionData) -> Vec<Sample> {
+    let days = match forecast_days_for(data, &data.dominentpol) {
+        Ok(days) => days,
+        Err(e) => {
+            tracing::debug!("Not recording the forecast of {}: {e}", data.city.name);
+            return Vec::new();
+        }
+    };
+    days.into_iter()
+        .filter_map(|(day, aqi)| {
+            Some(Sample {
+                date: NaiveDate::parse_from_str(&day, "%Y-%m-%d").ok()?,
+                aqi: aqi.aqi(),
+            })
+        })
+        .collect()
+}
+
+/// How far the last `ACCURACY_WINDOW_DAYS` forecasts that have a recorded reading were off.
+fn render_accuracy(city: &str, forecasts: &[Sample], history: &[Sample]) -> String {
+    let errors: Vec<f64> = forecasts
+        .iter()
+        .rev()
+        .filter_map(|forecast| {
+            let actual = history.iter().find(|s| s.date == forecast.date)?;
+            Some(forecast.aqi as f64 - actual.aqi as f64)
+        })
+        .take(ACCURACY_WINDOW_DAYS)
+        .collect();
+    if errors.is_empty() {
+        return format!(
+            "No forecast of {city} can be checked yet. Forecasts are saved whenever {city} is \
+             looked up and compared with the readings of the days they were for, check back in \
+             a few days."
+        );
+    }
+
+    let days = errors.len();
+    let bias = errors.iter().sum::<f64>() / days as f64;
+    let off_by = errors.iter().map(|error| error.abs()).sum::<f64>() / days as f64;
+    let direction = if bias.abs() < 1.0 {
+        "were right on average".to_owned()
+    } else if bias < 0.0 {
+        format!("ran ~{:.0} AQI low", -bias)
+    } else {
+        format!("ran ~{bias:.0} AQI high")
+    };
+    let span = if days == 1 {
+        "on the last day checked".to_owned()
+    } else {
+        format!("over the last {days} days checked")
+    };
+
+    format!("{city}: forecasts {direction} {span}, off by {off_by:.0} AQI per day on average.")
+}
+
+/// Every pollutant `sub_index_aqi` understands, worst first, as a monospace table. Values
+/// are concentrations where they can be worked out and labelled as sub-indices otherwise.
+fn render_pollutants(data: &PollutionData, settings: &UserSettings) -> String {
+    let rows = compute_all(&data.iaqi);
+
+    let city = html::escape(&data.city.name);
+    if rows.is_empty() {
+        return format!("{city} doesn't report any supported pollutant.");
+    }
+
+    let mut table = String::new();
+    for (name, aqi_level) in rows {
+        let value = DisplayValue::of(&name, data.iaqi[&name].v).to_string();
+        let level = level_from_aqi(&aqi_level, settings);
+        let (emoji, _) = air_quality_to_emoji(level, aqi_level.aqi());
+        table.push_str(&format!(
+            "{name:<5} {value:>14} AQI {:>3} {emoji} {level}\n",
+            aqi_level.aqi()
+        ));
+    }
+
+    format!("{city}\n<pre>{}</pre>", html::escape(&table))
+}
+
+/// `/limits`: what's left of the chat's lookup budget and cooldowns, and roughly how busy the
+/// bot is overall.
+fn render_limits(
+    chat_id: ChatId,
+    user_limiter: &UserLimiter,
+    cooldown: Duration,
+    waqi: &Waqi,
+) -> String {
+    let mut text = format!(
+        "Lookups: {} of {} left this minute\n",
+        user_limiter.lookups.remaining(&chat_id),
+        user_limiter.lookups.limit()
+    );
+    for name in HEAVY_COMMANDS {
+        let state = match user_limiter.heavy.remaining(&(chat_id, name), cooldown) {
+            // Whole seconds, rounded up so "0s" is never shown for a command still waiting.
+            Some(left) => format!(
+                "available in {}",
+                humantime::format_duration(Duration::from_secs(left.as_secs() + 1))
+            ),
+            None => "ready".to_owned(),
+        };
+        text.push_str(&format!("/{name}: {state}\n"));
+    }
+    let (left, limit) = waqi.global_headroom();
+    text.push_str(&format!(
+        "\nEveryone together: about {left} of {limit} WAQI requests left this minute. When \
+         they run out, lookups fail for everyone until the minute is over."
+    ));
+    text
+}
+
+/// `/tips`: the names and places lookups understand, since WAQI only matches station names
+/// exactly and falls back to a fuzzy search otherwise.
+const LOOKUP_TIPS: &str = "How to name a place:\n\n\
+    🏙️ A city in English, as WAQI spells it: /wis tehran, /wis new york. Local spellings \
+    and abbreviations often miss.\n\n\
+    🔎 When no station goes by the name, the closest search match is shown and named in the \
+    reply. If it's a regional average rather than the monitor you want, /wis tehran --alt \
+    shows the next best match.\n\n\
+    🆔 A station by its WAQI id, e.g. /wis @5287. /subscribe suggests the id of the closest \
+    match when a name isn't found.\n\n\
+    📍 Coordinates or a Google Maps link for the nearest station: /nearby 35.69,51.39 or \
+    /nearby https://maps.google.com/?q=35.69,51.39\n\n\
+    🗓️ Subscriptions take a name, an @id or geo:lat;lon: /subscribe geo:35.69;51.39 8";
+
+/// Splits `/past` arguments into the city and the day after it, which has to be before `today`.
+fn parse_past(args: &str, today: NaiveDate) -> Option<(String, NaiveDate)> {
+    let (city, date) = args.trim().rsplit_once(char::is_whitespace)?;
+    let date = match date.to_lowercase().as_str() {
+        "yesterday" => today.pred_opt()?,
+        date => NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?,
+    };
+    let city = input::clean_city(city);
+    (!city.is_empty() && date < today).then_some((city, date))
+}
+
+fn render_past(city: &str, date: NaiveDate, day: &HistoricalDay) -> String {
+    // Only readings beyond the top of the scale (500) have no level.
+    let level = AirQualityLevel::try_from(day.aqi).unwrap_or(AirQualityLevel::Hazardous);
+    let dominant = if day.dominentpol.is_empty() {
+        String::new()
+    } else {
+        format!(", mostly {}", pollutant_name(&day.dominentpol))
+    };
+    format!(
+        "{city} on {date}: AQI {} {}{dominant}",
+        day.aqi,
+        band_emoji(level)
+    )
+}
+
+/// Walks through what WAQI calls dominant, the AQI WAQI reports for each pollutant and where
+/// the headline AQI of the report comes from.
+fn render_explanation(data: &PollutionData, settings: &UserSettings) -> String {
+    let dominant = data.dominentpol.as_str();
+    let rows = compute_all(&data.iaqi);
+    let mut text = format!(
+        "{}\n\nWAQI reports an overall AQI of {} and calls {} the dominant pollutant, the one \
+         driving the reading.\n",
+        data.city.name,
+        data.aqi,
+        pollutant_name(dominant)
+    );
+
+    let Some((worst, worst_level)) = rows.first() else {
+        text.push_str(
+            "\nNone of the pollutants the station reports has a US AQI sub-index, so reports \
+             show WAQI's overall AQI as it is.",
+        );
+        return text;
+    };
+
+    text.push_str("\nThe AQI of each pollutant, as WAQI reports its sub-index:\n");
+    for (name, aqi_level) in &rows {
+        let level = level_from_aqi(aqi_level, settings);
+        let (emoji, _) = air_quality_to_emoji(level, aqi_level.aqi());
+        text.push_str(&format!(
+            "{}: AQI {} {emoji} {level}\n",
+            pollutant_name(name),
+            aqi_level.aqi()
+        ));
+    }
+
+    text.push('\n');
+    if normalize_pollutant(worst) == normalize_pollutant(dominant) {
+        text.push_str(&format!(
+            "{} is both dominant and the worst, so it sets the AQI of {} in your reports.",
+            pollutant_name(worst),
+            worst_level.aqi()
+        ));
+    } else {
+        text.push_str(&format!(
+            "{} has the highest AQI ({}), yet WAQI calls {} dominant. WAQI picks the dominant \
+             pollutant from its own figures, which can cover a different averaging period than \
+             the latest sub-indices used here, so the two don't always agree. ",
+            pollutant_name(worst),
+            worst_level.aqi(),
+            pollutant_name(dominant)
+        ));
+        match current_aqi(data) {
+            Ok(headline) => text.push_str(&format!(
+                "Reports follow WAQI and headline {} with an AQI of {}.",
+                pollutant_name(dominant),
+                headline.aqi()
+            )),
+            Err(e) => text.push_str(&format!("Reports can't headline it: {e}")),
+        }
+    }
+    if let Some(headline) = current_aqi(data).ok().filter(|h| h.aqi() != data.aqi) {
+        text.push_str(&format!(
+            "\n\nWAQI's overall AQI of {} is its own figure, while the {} in reports is the \
+             dominant pollutant's latest sub-index, so the two can differ.",
+            data.aqi,
+            headline.aqi()
+        ));
+    }
+    text
+}
+
+/// Position of a US band on the scale, 0 for the cleanest.
+fn us_rank(level: AirQualityLevel) -> usize {
+    use AirQualityLevel::*;
+
+    match level {
+        Good => 0,
+        Moderate => 1,
+        UnhealthySensitive => 2,
+        Unhealthy => 3,
+        VeryUnhealthy => 4,
+        Hazardous => 5,
+    }
+}
+
+fn render_stats(waqi: &Waqi) -> String {
+    let mut text = format!(
+        "Slow WAQI fetches: {}\n\nWAQI tokens:\n",
+        waqi.slow_fetches()
+    );
+    for (i, health) in waqi.token_health().iter().enumerate() {
+        let status = match health.cooling_down_for {
+            Some(remaining) => format!("over quota, retrying in {}m", remaining.as_secs() / 60 + 1),
+            None => "ok".to_owned(),
+        };
+        text.push_str(&format!(
+            "#{} {}: {} requests, {} over quota, {status}\n",
+            i + 1,
+            health.label,
+            health.requests,
+            health.over_quota
+        ));
+    }
+    text
+}
+
+/// A sample of every glyph replies use, one band per line.
+fn render_emoji_test() -> String {
+    let mut text = String::from("Bands:\n");
+    for aqi in [25, 75, 125, 175, 250, 400] {
+        if let Ok(level) = AirQualityLevel::try_from(aqi) {
+            let (emoji, progress_bar) = air_quality_to_emoji(level, aqi);
+            text.push_str(&format!("{emoji} {level}\n{progress_bar}\n"));
+        }
+    }
+
+    text.push_str(&format!("\nLadder:\n{}\n", render_ladder()));
+    text.push_str("\nEU CAQI: 💚 💛 🧡 ❤️ 💜\n");
+
+    let uv: Vec<&str> = [1, 4, 7, 9, 11]
+        .into_iter()
+        .map(|uvi| uv_category(uvi).0)
+        .collect();
+    text.push_str(&format!("UV: {}\n", uv.join(" ")));
+
+    let conditions: Vec<&str> = CONDITIONS
+        .iter()
+        .filter_map(|(_, label, _)| label.split(' ').next())
+        .collect();
+    text.push_str(&format!(
+        "\nOther: ⚠️ ✅ 🚬 🗓️ {}\n\n",
+        conditions.join(" ")
+    ));
+
+    text.push_str(
+        "If any of these show up as boxes or question marks, your client lacks the font for them.",
+    );
+    text
+}
+
+/// `url` without the credentials of a proxy in front of WAQI it may carry.
+fn redact_url(url: &str) -> String {
+    match reqwest::Url::parse(url) {
+        Ok(mut url) => {
+            if !url.username().is_empty() {
+                let _ = url.set_username("***");
+            }
+            if url.password().is_some() {
+                let _ = url.set_password(Some("***"));
+            }
+            if url.query().is_some() {
+                url.set_query(Some("***"));
+            }
+            url.to_string()
+        }
+        Err(_) => "(invalid)".to_owned(),
+    }
+}
+
+/// Everything `/probe` learned about fetching `target`.
+fn render_probe(target: &str, probe: &Probe) -> String {
+    let mut text = format!(
+        "Probe of {target}\n\
+         Cached: {}\n\
+         Took: {}ms\n",
+        if probe.cached { "yes" } else { "no" },
+        probe.elapsed.as_millis()
+    );
+    match &probe.response {
+        Some(response) => text.push_str(&format!(
+            "HTTP {} from {}\n\
+             Body: {} bytes\n",
+            response.status, response.host, response.body_bytes
+        )),
+        None => text.push_str("No response\n"),
+    }
+    match &probe.result {
+        Ok(data) => {
+            let observed = observed_at(&data.time).map_or("unknown".to_owned(), |time| {
+                format!("{time} ({})", data.time.s)
+            });
+            text.push_str(&format!(
+                "\nParsed: {} (@{})\n\
+                 AQI: {}, dominant: {}\n\
+                 Pollutants: {}\n\
+                 Forecast: {}\n\
+                 Measured: {observed}\n\
+                 Sources: {}",
+                data.city.name,
+                data.idx,
+                data.aqi,
+                data.dominentpol,
+                data.iaqi.len(),
+                forecast_days(data).map_or_else(
+                    |e| format!("unusable ({e})"),
+                    |days| match days.len() {
+                        1 => "1 day".to_owned(),
+                        days => format!("{days} days"),
+                    }
+                ),
+                data.attributions.len()
+            ));
+        }
+        Err(e) => text.push_str(&format!("\nFailed: {e}")),
+    }
+    text
+}
+
+/// The configuration the bot is running with. Tokens are only counted, never shown.
+fn render_debug(config: &Config) -> String {
+    let base_url = redact_url(config.api_base());
+    let fallback_base_url = config
+        .api_fallback_base()
+        .map_or("none".to_owned(), redact_url);
+    let health_check = match config.health_check_interval_secs {
+        0 => "off".to_owned(),
+        secs => format!(
+            "{} every {secs}s, alerting after {} failures",
+            config.health_check_city, config.health_check_failures
+        ),
+    };
+    let cache_ttl = match config.cache_ttl_secs {
+        0 => "off".to_owned(),
+        secs => humantime::format_duration(Duration::from_secs(secs)).to_string(),
+    };
+    let persisted_cache = if config.persist_cache {
+        config.cache_file.as_str()
+    } else {
+        "off"
+    };
+
+    format!(
+        "Base URL: {base_url}\n\
+         Fallback base URL: {fallback_base_url}\n\
+         Request timeout: {}\n\
+         Cache TTL: {cache_ttl}\n\
+         Persisted cache: {persisted_cache}\n\
+         Unknown city TTL: {}s\n\
+         Command timeout: {}s\n\
+         Slow fetch threshold: {}ms\n\
+         Inter-request delay: {}ms\n\
+         Rate limits: {} per user, {} global (per minute)\n\
+         Heavy command cooldown: {}s\n\
+         Scheduler concurrency: {}\n\
+         Health check: {}\n\
+         AQI standard: US EPA\n\
+         Default language: {}\n\
+         JSON logs: off\n\
+         Update check: {}\n\
+         Maintenance mode: {}\n\
+         WAQI tokens: {}\n\
+         Admins: {}",
+        humantime::format_duration(waqi::REQUEST_TIMEOUT),
+        config.not_found_ttl_secs,
+        config.command_timeout_secs,
+        config.slow_fetch_ms,
+        config.inter_request_delay_ms,
+        config.rate_limit.per_user_per_minute,
+        config.rate_limit.global_per_minute,
+        config.rate_limit.heavy_command_cooldown_secs,
+        config.scheduler_concurrency,
+        health_check,
+        config.default_language,
+        if config.check_updates { "on" } else { "off" },
+        if config.maintenance_mode { "on" } else { "off" },
+        config.aqi_tokens().len(),
+        config.admin_chat_ids.len()
+    )
+}
+
+/// What `/reload` changed, and what it couldn't because it needs a restart.
+fn render_config_changes(old: &Config, new: &Config) -> String {
+    let mut applied = Vec::new();
+    let mut changed = |name: &str, old: String, new: String| {
+        if old != new {
+            applied.push(format!("{name}: {old} → {new}"));
+        }
+    };
+    changed(
+        "cache_ttl_secs",
+        old.cache_ttl_secs.to_string(),
+        new.cache_ttl_secs.to_string(),
+    );
+    changed(
+        "not_found_ttl_secs",
+        old.not_found_ttl_secs.to_string(),
+        new.not_found_ttl_secs.to_string(),
+    );
+    changed(
+        "command_timeout_secs",
+        old.command_timeout_secs.to_string(),
+        new.command_timeout_secs.to_string(),
+    );
+    changed(
+        "slow_fetch_ms",
+        old.slow_fetch_ms.to_string(),
+        new.slow_fetch_ms.to_string(),
+    );
+    changed(
+        "check_updates",
+        old.check_updates.to_string(),
+        new.check_updates.to_string(),
+    );
+    changed(
+        "inter_request_delay_ms",
+        old.inter_request_delay_ms.to_string(),
+        new.inter_request_delay_ms.to_string(),
+    );
+    changed(
+        "rate_limit.per_user_per_minute",
+        old.rate_limit.per_user_per_minute.to_string(),
+        new.rate_limit.per_user_per_minute.to_string(),
+    );
+    changed(
+        "rate_limit.global_per_minute",
+        old.rate_limit.global_per_minute.to_string(),
+        new.rate_limit.global_per_minute.to_string(),
+    );
+    changed(
+        "rate_limit.heavy_command_cooldown_secs",
+        old.rate_limit.heavy_command_cooldown_secs.to_string(),
+        new.rate_limit.heavy_command_cooldown_secs.to_string(),
+    );
+    changed(
+        "default_language",
+        old.default_language.clone(),
+        new.default_language.clone(),
+    );
+    changed(
+        "health_check_city",
+        old.health_check_city.clone(),
+        new.health_check_city.clone(),
+    );
+    changed(
+        "health_check_failures",
+        old.health_check_failures.to_string(),
+        new.health_check_failures.to_string(),
+    );
+    changed(
+        "maintenance_mode",
+        old.maintenance_mode.to_string(),
+        new.maintenance_mode.to_string(),
+    );
+    changed(
+        "maintenance_message",
+        old.maintenance_message.clone(),
+        new.maintenance_message.clone(),
+    );
+    changed(
+        "admin_chat_ids",
+        format!("{:?}", old.admin_chat_ids),
+        format!("{:?}", new.admin_chat_ids),
+    );
+
+    let mut restart = Vec::new();
+    if old.telegram_token != new.telegram_token {
+        restart.push("telegram_token");
+    }
+    if old.aqi_tokens() != new.aqi_tokens() {
+        restart.push("aqi_token / aqi_tokens");
+    }
+    if old.base_url != new.base_url {
+        restart.push("base_url");
+    }
+    if old.fallback_base_url != new.fallback_base_url {
+        restart.push("fallback_base_url");
+    }
+    if old.scheduler_concurrency != new.scheduler_concurrency {
+        restart.push("scheduler_concurrency");
+    }
+    if old.health_check_interval_secs != new.health_check_interval_secs {
+        restart.push("health_check_interval_secs");
+    }
+    if old.status_port != new.status_port {
+        restart.push("status_port");
+    }
+    if old.ready_requires_waqi != new.ready_requires_waqi {
+        restart.push("ready_requires_waqi");
+    }
+    if old.persist_cache != new.persist_cache {
+        restart.push("persist_cache");
+    }
+    if old.cache_file != new.cache_file {
+        restart.push("cache_file");
+    }
+
+    let mut text = if applied.is_empty() {
+        String::from("Configuration reloaded, nothing changed.\n")
+    } else {
+        format!("Configuration reloaded:\n{}\n", applied.join("\n"))
+    };
+    if !restart.is_empty() {
+        text.push_str(&format!(
+            "\nThese changed too but need a restart: {}",
+            restart.join(", ")
+        ));
+    }
+    text
+}
+
+/// `time` in the user's time zone, the server's if they haven't set one, on the user's clock.
+fn format_local(time: DateTime<Utc>, settings: &UserSettings) -> String {
+    let pattern = format!("%Y-%m-%d {}", settings.time_format.pattern());
+    match settings.timezone {
+        Some(tz) => time.with_timezone(&tz).format(&pattern).to_string(),
+        None => time.with_timezone(&Local).format(&pattern).to_string(),
+    }
+}
+
+/// The band of `aqi_level`, on the user's own cutoffs if they've set any.
+fn level_from_aqi(aqi_level: &AirQuality, settings: &UserSettings) -> AirQualityLevel {
+    use AirQualityLevel::*;
+
+    let Some(cutoffs) = settings.band_cutoffs else {
+        return aqi_level.level();
+    };
+    let levels = [Good, Moderate, UnhealthySensitive, Unhealthy, VeryUnhealthy];
+    cutoffs
+        .iter()
+        .zip(levels)
+        .find(|(cutoff, _)| aqi_level.aqi() <= **cutoff)
+        .map_or(Hazardous, |(_, level)| level)
+}
+
+/// Highest AQI of each band from Good to Very Unhealthy on the US EPA scale.
+const STANDARD_BAND_CUTOFFS: [u32; 5] = [50, 100, 150, 200, 300];
+
+/// Top of the scale, where the Hazardous band ends.
+const MAX_SCALE_AQI: u32 = 500;
+
+/// How far into its band (from the user's cutoffs or the standard ones) `aqi_level` sits,
+/// from 0 at the bottom of the band to 1 at the top.
+fn band_position(aqi_level: &AirQuality, settings: &UserSettings) -> f64 {
+    let cutoffs = settings.band_cutoffs.unwrap_or(STANDARD_BAND_CUTOFFS);
+    let aqi = aqi_level.aqi();
+    let mut lower = 0;
+    for upper in cutoffs
+        .into_iter()
+        .chain([MAX_SCALE_AQI.max(cutoffs[4].saturating_add(1))])
+    {
+        if aqi <= upper {
+            return f64::from(aqi - lower) / f64::from(upper - lower);
+        }
+        lower = upper;
+    }
+    1.0
+}
+
+/// "🔸" for readings in the upper half of their band, its midpoint included, if the user
+/// turned band markers on, so two readings with the same emoji can still be told apart.
+fn band_marker(aqi_level: &AirQuality, settings: &UserSettings) -> &'static str {
+    if settings.band_markers && band_position(aqi_level, settings) >= 0.5 {
+        "🔸"
+    } else {
+        ""
+    }
+}
+
+/// Parses `/bands` cutoffs: five strictly increasing AQI values, none above the highest AQI a
+/// reading can plausibly have.
+fn parse_band_cutoffs(args: &str) -> Result<[u32; 5], String> {
+    let values = args
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|value| !value.is_empty())
+        .map(|value| {
+            value
+                .parse::<u32>()
+                .map_err(|_| format!("{value} isn't a whole number"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let cutoffs: [u32; 5] = values
+        .try_into()
+        .map_err(|values: Vec<u32>| format!("Expected 5 cutoffs, got {}", values.len()))?;
+    if cutoffs[0] == 0 || cutoffs.windows(2).any(|pair| pair[0] >= pair[1]) {
+        return Err("Cutoffs must be above 0 and strictly increasing".into());
+    }
+    if cutoffs[4] > model::MAX_PLAUSIBLE_AQI {
+        return Err(format!(
+            "Cutoffs can't be above {}",
+            model::MAX_PLAUSIBLE_AQI
+        ));
+    }
+    Ok(cutoffs)
+}
+
+/// The station with the lowest AQI within `radius_km` of `origin`.
+fn render_cleanest(stations: &[MapStation], origin: (f64, f64), radius_km: f64) -> String {
+    let cleanest = stations
+        .iter()
+        .filter_map(|station| {
+            let aqi = station.aqi()?;
+            let distance = distance_km(origin, (station.lat, station.lon));
+            (distance <= radius_km).then_some((station, aqi, distance))
+        })
+        .min_by(|a, b| a.1.cmp(&b.1).then(a.2.total_cmp(&b.2)));
+
+    let Some((station, aqi, distance)) = cleanest else {
+        return format!("No stations with a current reading within {radius_km:.0}km.");
+    };
+    let emoji = AirQualityLevel::try_from(aqi)
+        .map(|level| air_quality_to_emoji(level, aqi).0)
+        .unwrap_or_default();
+    format!(
+        "Cleanest air near you: {} {emoji} (AQI {aqi}), {distance:.0}km away",
+        station.station.name
+    )
+}
+
+/// Great-circle distance between two `(lat, lon)` points.
+fn distance_km(from: (f64, f64), to: (f64, f64)) -> f64 {
+    const EARTH_RADIUS_KM: f64 = 6371.0;
+    let (lat1, lat2) = (from.0.to_radians(), to.0.to_radians());
+    let dlat = lat2 - lat1;
+    let dlon = (to.1 - from.1).to_radians();
+    let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+}
+
+/// Parses the argument of an on/off setting command.
+fn parse_toggle(arg: &str) -> Option<bool> {
+    match arg.trim().to_lowercase().as_str() {
+        "on" | "yes" | "true" | "1" => Some(true),
+        "off" | "no" | "false" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// Consumes one lookup from the chat's budget, telling the user when they've run out.
+async fn check_rate_limit(
+    bot: &ChatBot,
+    chat_id: ChatId,
+    limiter: &UserLimiter,
+) -> ResponseResult<bool> {
+    if limiter.lookups.try_acquire(chat_id) {
+        return Ok(true);
+    }
+    bot.send_message(
+        chat_id,
+        "You're sending lookups too quickly, please wait a minute.",
+    )
+    .await?;
+    Ok(false)
+}
+
+/// `/wis` arguments: a city name mixed with `--option value` flags.
+struct WisArgs {
+    city: String,
+    /// Refuse readings older than this instead of showing them.
+    max_age: Option<Duration>,
+    /// Overrides the user's `forecast_days` for this lookup.
+    days: Option<u8>,
+    /// Pin the report, replacing the one pinned before.
+    pin: bool,
+    /// Skip the station WAQI picks by default for the next best search match.
+    alt: bool,
+    /// Follow the report with the reading on both the US EPA and EU CAQI scales, asked for
+    /// with a `@both` suffix on the city.
+    both_standards: bool,
+}
+
+/// Suffix of a `/wis` city asking for the reading on both scales.
+const BOTH_STANDARDS_SUFFIX: &str = "@both";
+
+fn parse_wis_args(args: &str) -> Result<WisArgs, String> {
+    let mut city = Vec::new();
+    let mut max_age = None;
+    let mut days = None;
+    let mut pin = false;
+    let mut alt = false;
+
+    let mut tokens = args.split_whitespace();
+    while let Some(token) = tokens.next() {
+        match token {
+            "--max-age" => {
+                let value = tokens
+                    .next()
+                    .ok_or("--max-age needs a duration, e.g. --max-age 1h")?;
+                let duration = humantime::parse_duration(value)
+                    .map_err(|e| format!("Invalid --max-age ({value}): {e}"))?;
+                max_age = Some(duration);
+            }
+            "--days" => {
+                let value = tokens
+                    .next()
+                    .ok_or("--days needs a number, e.g. --days 3")?;
+                days = Some(parse_forecast_days(value)?);
+            }
+            "--pin" => pin = true,
+            "--alt" => alt = true,
+            flag if flag.starts_with("--") => return Err(format!("Unknown option: {flag}")),
+            word => city.push(word),
+        }
+    }
+
+    let mut city = input::clean_city(&city.join(" "));
+    let suffix_start = city.len().saturating_sub(BOTH_STANDARDS_SUFFIX.len());
+    let both_standards = city
+        .get(suffix_start..)
+        .is_some_and(|suffix| suffix.eq_ignore_ascii_case(BOTH_STANDARDS_SUFFIX));
+    if both_standards {
+        city.truncate(suffix_start);
+        city.truncate(city.trim_end().len());
+    }
+
+    Ok(WisArgs {
+        city,
+        max_age,
+        days,
+        pin,
+        alt,
+        both_standards,
+    })
+}
+
+/// Sends the `/wis` report of `args.city`, ending with a link that shows the same city to
+/// whoever opens it.
+async fn send_city_report(
+    bot: &ChatBot,
+    chat_id: ChatId,
+    waqi: &Waqi,
+    store: &Store,
+    args: &WisArgs,
+    settings: &UserSettings,
+    me: &Me,
+) -> ResponseResult<Message> {
+    let result = match get_city_report(waqi, store, chat_id, args, settings).await {
+        Ok(text) => match me
+            .username
+            .as_deref()
+            .and_then(|bot_name| deeplink::city_link(bot_name, &args.city))
+        {
+            Some(link) => format!("{text}\n\nShare this reading: {link}"),
+            None => text,
+        },
+        Err(e) => match e.downcast_ref::<WaqiError>() {
+            Some(demo @ WaqiError::Demo) => {
+                tracing::warn!("{e}");
+                demo.to_string()
+            }
+            Some(non_json @ WaqiError::NonJson { .. }) => failure_reply(
+                format!("Couldn't get data for {}: {non_json}", args.city),
+                &e,
+            ),
+            _ => failure_reply(format!("Couldn't get data for {}", args.city), &e),
+        },
+    };
+    // A preview card of the bot under every report would only push the reading out of view.
+    bot.send_message(chat_id, result)
+        .link_preview_options(no_link_preview())
+        .await
+}
+
+/// Sends links as plain text, without a preview card of the first one.
+fn no_link_preview() -> LinkPreviewOptions {
+    LinkPreviewOptions {
+        is_disabled: true,
+        url: None,
+        prefer_small_media: false,
+        prefer_large_media: false,
+        show_above_text: false,
+    }
+}
+
+/// Sends `text` as HTML. When Telegram rejects the markup, the text is logged and sent again
+/// without it, so a formatting bug costs the user the layout rather than the reading.
+async fn send_html(bot: &ChatBot, chat_id: ChatId, text: String) -> ResponseResult<Message> {
+    match bot
+        .send_message(chat_id, text.clone())
+        .parse_mode(ParseMode::Html)
+        .await
+    {
+        Err(RequestError::Api(ApiError::CantParseEntities(e))) => {
+            tracing::error!("Telegram rejected the HTML of a reply ({e}): {text:?}");
+            bot.send_message(chat_id, strip_html(&text)).await
+        }
+        result => result,
+    }
+}
+
+/// `text` without its HTML tags and with the entities `html::escape` writes decoded.
+fn strip_html(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut in_tag = false;
+    for c in text.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => plain.push(c),
+            _ => {}
+        }
+    }
+    plain
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&amp;", "&")
+}
+
+/// Pins `report` and unpins the report pinned by the previous `/wis --pin`. Pinning in groups
+/// needs admin rights, so the user is told when the bot lacks them.
+async fn pin_report(
+    bot: &ChatBot,
+    store: &Store,
+    settings: &UserSettings,
+    report: &Message,
+) -> ResponseResult<()> {
+    let chat_id = report.chat.id;
+    match bot
+        .pin_chat_message(chat_id, report.id)
+        .disable_notification(true)
+        .await
+    {
+        Ok(_) => {}
+        Err(RequestError::Api(
+            ApiError::NotEnoughRightsToPinMessage | ApiError::NotEnoughRightsToManagePins,
+        )) => {
+            bot.send_message(
+                chat_id,
+                "I couldn't pin the report, I need to be an admin allowed to pin messages here.",
+            )
+            .await?;
+            return Ok(());
+        }
+        Err(e) => return Err(e),
+    }
+
+    if let Some(previous) = settings.pinned_report.filter(|id| *id != report.id.0) {
+        // The previous report may have been unpinned or deleted by hand since.
+        if let Err(e) = bot
+            .unpin_chat_message(chat_id)
+            .message_id(MessageId(previous))
+            .await
+        {
+            tracing::debug!("Couldn't unpin report {previous} in {chat_id}: {e}");
+        }
+    }
+    store
+        .update_settings(chat_id, |s| s.pinned_report = Some(report.id.0))
+        .await;
+    Ok(())
+}
+
+/// Parses a number of forecast days, which has to be within 1..=MAX_FORECAST_DAYS.
+fn parse_forecast_days(value: &str) -> Result<u8, String> {
+    match value.parse::<u8>() {
+        Ok(days) if (1..=MAX_FORECAST_DAYS).contains(&days) => Ok(days),
+        _ => Err(format!(
+            "The number of days ({value}) must be between 1 and {MAX_FORECAST_DAYS}"
+        )),
+    }
+}
+
+async fn get_city_report(
+    waqi: &Waqi,
+    store: &Store,
+    chat_id: ChatId,
+    args: &WisArgs,
+    settings: &UserSettings,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let (data, closest_match) = if args.alt {
+        match waqi.feed_alternative(&args.city).await {
+            Ok((data, name)) => (data, Some(name)),
+            Err(WaqiError::CityNotFound) => {
+                return Ok(format!("No other station matches {}.", args.city))
+            }
+            Err(e) => return Err(e.into()),
+        }
+    } else {
+        waqi.feed_or_closest(&args.city).await?
+    };
+    let mut percentile = None;
+    // A closest match is recorded under its station id, the key `/subscribe @id` and the
+    // scheduler use for it, rather than under a name that found no station.
+    let key = match closest_match {
+        Some(_) => Target::Named(format!("@{}", data.idx)).key(),
+        None => args.city.clone(),
+    };
+    // The city's history tracks its default station, which an alternative isn't.
+    if !args.alt {
+        store.record_forecast(&key, forecast_samples(&data)).await;
+    }
+    if let Some(sample) = sample_of(&data).filter(|_| !args.alt) {
+        store.record_sample(&key, sample.clone()).await;
+        if settings.verbosity != Verbosity::Brief {
+            percentile = percentile_line(&sample, &store.history(&key).await);
+        }
+    }
+    let label = if args.alt {
+        "Showing alternative station"
+    } else {
+        "Showing closest match"
+    };
+    let note = closest_match.map_or(String::new(), |name| format!("{label}: {name}\n\n"));
+
+    if let Some(max_age) = args.max_age {
+        match reading_age(&data) {
+            Some(age) if age <= max_age => {}
+            Some(age) => {
+                return Ok(format!(
+                    "{note}No fresh data available for {}. The latest reading is {} old.",
+                    data.city.name,
+                    format_age(age)
+                ))
+            }
+            None => {
+                return Ok(format!(
+                    "{note}No fresh data available for {}. The reading has no usable timestamp.",
+                    data.city.name
+                ))
+            }
+        }
+    }
+
+    let mut settings = settings.clone();
+    if args.days.is_some() {
+        settings.forecast_days = args.days;
+    }
+    // Only the first lookup picks the standard, later ones go by what it stored.
+    if settings.infer_standard(data.city.coords()) {
+        let standard = settings.standard;
+        store
+            .update_settings(chat_id, |s| {
+                if s.standard.is_none() {
+                    s.standard = standard;
+                }
+            })
+            .await;
+    }
+
+    let standards = if args.both_standards {
+        match render_standards(&data) {
+            Ok(text) => format!("\n\n{text}"),
+            Err(e) => {
+                tracing::warn!("{e}");
+                "\n\nThis reading can't be compared across scales.".to_owned()
+            }
+        }
+    } else if settings.standard == Some(Standard::Eu) {
+        render_caqi(&data).map_or(String::new(), |line| format!("\n\n{line}"))
+    } else {
+        String::new()
+    };
+
+    Ok(format!(
+        "{note}{}{}{standards}",
+        render_report(&data, &settings)?,
+        percentile.unwrap_or_default()
+    ))
+}
+
+async fn get_city_pollution_emoji(
+    waqi: &Waqi,
+    city: &str,
+    settings: &UserSettings,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let data = waqi.feed(city).await?;
+
+    render_report(&data, settings)
+}
+
+/// Fetches and renders the digest sent for `subscriptions`, used by both the scheduler and
+/// `/testdigest`. Cities not fetched by `deadline` are left out with a note, so the rest still
+/// gets sent.
+async fn render_digest(
+    waqi: &Waqi,
+    subscriptions: &[Subscription],
+    settings: &UserSettings,
+    deadline: tokio::time::Instant,
+) -> Vec<String> {
+    let delay = waqi.inter_request_delay();
+    let mut readings = HashMap::new();
+    for (i, subscription) in subscriptions.iter().enumerate() {
+        if i > 0 && !delay.is_zero() {
+            tokio::time::sleep_until(deadline.min(tokio::time::Instant::now() + delay)).await;
+        }
+        let target = subscription.target.feed_target();
+        let reading = match tokio::time::timeout_at(deadline, waqi.feed(&target)).await {
+            Ok(reading) => reading.map_err(|e| e.to_string()),
+            Err(_) => Err(format!("Timed out fetching {target}")),
+        };
+        readings.insert(subscription.target.key(), reading);
+    }
+    format_digest(subscriptions, &readings, settings)
+}
+
+/// The digest's messages from readings fetched beforehand, keyed by `Target::key`: one in
+/// total, or one per city, depending on `digest_layout`.
+fn format_digest(
+    subscriptions: &[Subscription],
+    readings: &HashMap<String, Result<PollutionData, String>>,
+    settings: &UserSettings,
+) -> Vec<String> {
+    let header = "🗓️ Your air quality digest\n";
+    let reports = subscriptions.iter().map(|subscription| {
+        let report = match readings.get(&subscription.target.key()) {
+            Some(Ok(data)) => render_report(data, settings).map_err(|e| e.to_string()),
+            Some(Err(e)) => Err(e.clone()),
+            None => Err(format!("No reading fetched for {}", subscription.target)),
+        };
+        report.unwrap_or_else(|e| {
+            let reply = failure_reply(format!("Couldn't get data for {}", subscription.target), e);
+            format!("{reply}\n")
+        })
+    });
+
+    match settings.digest_layout {
+        DigestLayout::Separate => reports
+            .map(|report| format!("{header}\n{report}"))
+            .collect(),
+        DigestLayout::Combined => {
+            let mut text = header.to_owned();
+            if let Some(comparison) = render_comparison(subscriptions, readings, settings) {
+                text.push('\n');
+                text.push_str(&comparison);
+            }
+            for report in reports {
+                text.push('\n');
+                text.push_str(&report);
+            }
+            vec![text]
+        }
+    }
+}
+
+/// The digest's cities side by side, cleanest first, when more than one has a reading.
+fn render_comparison(
+    subscriptions: &[Subscription],
+    readings: &HashMap<String, Result<PollutionData, String>>,
+    settings: &UserSettings,
+) -> Option<String> {
+    let mut rows: Vec<(&str, AirQuality)> = subscriptions
+        .iter()
+        .filter_map(
+            |subscription| match readings.get(&subscription.target.key()) {
+                Some(Ok(data)) => Some((data.city.name.as_str(), current_aqi(data).ok()?)),
+                _ => None,
+            },
+        )
+        .collect();
+    if rows.len() < 2 {
+        return None;
+    }
+    rows.sort_by_key(|(_, aqi_level)| aqi_level.aqi());
+
+    let mut text = String::new();
+    for (name, aqi_level) in &rows {
+        let level = level_from_aqi(aqi_level, settings);
+        let (emoji, _) = air_quality_to_emoji(level, aqi_level.aqi());
+        text.push_str(&format!(
+            "{emoji} {name}: {level} (AQI {})\n",
+            aqi_level.aqi()
+        ));
+    }
+    text.push_str(&format!("Cleanest air: {}\n", rows[0].0));
+    Some(text)
+}
+
+/// Largest file `/importsettings` accepts.
+const MAX_IMPORT_BYTES: u32 = 64 * 1024;
+
+/// Checks an uploaded export the way the individual commands would have checked each value.
+/// The pinned report belongs to this chat, so it's kept rather than imported.
+fn validate_import(mut export: UserExport, current: &UserSettings) -> Result<UserExport, String> {
+    let settings = &mut export.settings;
+    if let Some(pollutant) = &settings.focus_pollutant {
+        if !SUPPORTED_POLLUTANTS.contains(&pollutant.as_str()) {
+            return Err(format!("unsupported focus pollutant {pollutant}"));
+        }
+    }
+    if let Some(days) = settings.forecast_days {
+        parse_forecast_days(&days.to_string())?;
+    }
+    if let Some(cutoffs) = settings.band_cutoffs {
+        let cutoffs = cutoffs.map(|cutoff| cutoff.to_string()).join(" ");
+        parse_band_cutoffs(&cutoffs)?;
+    }
+    settings.pinned_report = current.pinned_report;
+
+    if export.subscriptions.len() > MAX_SUBSCRIPTIONS {
+        return Err(format!("more than {MAX_SUBSCRIPTIONS} subscriptions"));
+    }
+    for subscription in &mut export.subscriptions {
+        if subscription.hour >= 24 {
+            return Err(format!("invalid hour {}", subscription.hour));
+        }
+        match &mut subscription.target {
+            Target::Named(city) => {
+                *city = input::clean_city(city.as_str());
+                if city.is_empty() {
+                    return Err("a subscription has no city".into());
+                }
+            }
+            Target::Geo(lat, lon) => {
+                if !((-90.0..=90.0).contains(&*lat) && (-180.0..=180.0).contains(&*lon)) {
+                    return Err(format!("invalid coordinates {lat},{lon}"));
+                }
+            }
+        }
+    }
+    Ok(export)
+}
+
+/// Parses `/subscribe` arguments: a target followed by the hour to deliver at, or by
+/// `onchange` for band-change alerts.
+fn parse_subscription(args: &str) -> Option<Subscription> {
+    let (target, when) = args.trim().rsplit_once(char::is_whitespace)?;
+    let (hour, mode) = match when.to_lowercase().as_str() {
+        "onchange" | "on_band_change" => (0, AlertMode::BandChange),
+        "onforecast" | "on_forecast_worse" => (0, AlertMode::ForecastWorse),
+        hour => (hour.trim_end_matches(":00").parse().ok()?, AlertMode::Daily),
+    };
+    let target = parse_target(target);
+
+    let valid = match &target {
+        Target::Named(city) => !city.is_empty(),
+        Target::Geo(..) => true,
+    };
+    (hour < 24 && valid).then_some(Subscription {
+        target,
+        hour,
+        mode,
+        last_band: None,
+        station: None,
+        coords: None,
+        announced_days: Vec::new(),
+    })
+}
+
+/// The forecast days a forecast alert has warned about that are still ahead, along with the
+/// alert to send if the forecast has a day in a worse band than now that wasn't announced yet.
+fn forecast_alert(
+    subscription: &Subscription,
+    data: &PollutionData,
+    settings: &UserSettings,
+) -> Result<(Vec<String>, Option<String>), String> {
+    let current = us_rank(level_from_aqi(&current_aqi(data)?, settings));
+    let days = forecast_days(data).map_err(|e| e.to_string())?;
+
+    let mut announced: Vec<String> = subscription
+        .announced_days
+        .iter()
+        .filter(|day| days.iter().any(|(upcoming, _)| upcoming == *day))
+        .cloned()
+        .collect();
+    let mut lines = Vec::new();
+    for (day, aqi_level) in days {
+        let level = level_from_aqi(&aqi_level, settings);
+        if us_rank(level) <= current || announced.contains(&day) {
+            continue;
+        }
+        let weekday = NaiveDate::parse_from_str(&day, "%Y-%m-%d")
+            .map_or_else(|_| day.clone(), |date| date.format("%A").to_string());
+        let (emoji, _) = air_quality_to_emoji(level, aqi_level.aqi());
+        lines.push(format!(
+            "⚠️ Heads up: {weekday}'s forecast for {} is {level} {emoji} (AQI {})",
+            data.city.name,
+            aqi_level.aqi()
+        ));
+        announced.push(day);
+    }
+    Ok((announced, (!lines.is_empty()).then(|| lines.join("\n"))))
+}
+
+/// Splits `/alertregion` arguments into the latitude and longitude ranges of the box spanned
+/// by two corners and the alert to send.
+fn parse_region_alert(args: &str) -> Option<(RangeInclusive<f64>, RangeInclusive<f64>, String)> {
+    let (bounds, text) = args.trim().split_once(char::is_whitespace)?;
+    let text = text.trim();
+    let corners = bounds
+        .split(',')
+        .map(|value| value.trim().parse::<f64>().ok().filter(|v| v.is_finite()))
+        .collect::<Option<Vec<f64>>>()?;
+    let [lat1, lon1, lat2, lon2] = corners[..] else {
+        return None;
+    };
+    (!text.is_empty()).then(|| {
+        (
+            lat1.min(lat2)..=lat1.max(lat2),
+            lon1.min(lon2)..=lon1.max(lon2),
+            text.to_owned(),
+        )
+    })
+}
+
+/// The band of a band-change alert's new reading, along with the alert to send if it moved
+/// since `subscription.last_band`. The first reading only sets the baseline.
+fn band_change_alert(
+    subscription: &Subscription,
+    data: &PollutionData,
+    settings: &UserSettings,
+) -> Result<(u8, Option<String>), String> {
+    let aqi_level = current_aqi(data)?;
+    let level = level_from_aqi(&aqi_level, settings);
+    let band = us_rank(level) as u8;
+    let Some(last_band) = subscription.last_band.filter(|last| *last != band) else {
+        return Ok((band, None));
+    };
+
+    let (emoji, _) = air_quality_to_emoji(level, aqi_level.aqi());
+    let (arrow, direction) = if band > last_band {
+        ("📈", "worsened")
+    } else {
+        ("📉", "improved")
+    };
+    Ok((
+        band,
+        Some(format!(
+            "{arrow} Air quality in {} {direction} to {level} {emoji} (AQI {})",
+            data.city.name,
+            aqi_level.aqi()
+        )),
+    ))
+}
+
+/// Parses a subscription target: `geo:lat;lon`, anything `/nearby` understands, or a city name.
+fn parse_target(input: &str) -> Target {
+    let input = input.trim();
+    let coords = match input.strip_prefix("geo:") {
+        Some(pair) => parse_lat_lon(&pair.replace(';', ",")),
+        None => parse_coordinates(input),
+    };
+    match coords {
+        Some((lat, lon)) => Target::Geo(lat, lon),
+        None => Target::Named(input::clean_city(input)),
+    }
+}
+
+/// Renders the forecast of `city` as a PNG, or `None` if the station has no forecast.
+async fn get_city_heatmap(
+    waqi: &Waqi,
+    city: &str,
+) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+    let data = waqi.feed(city).await?;
+
+    let days = forecast_days(&data)?;
+    if days.is_empty() {
+        return Ok(None);
+    }
+
+    let title = format!("{} ({})", data.city.name, data.dominentpol);
+    Ok(Some(chart::forecast_heatmap(&title, &days)?))
+}
+
+/// The distinct cities of a comma-separated `/avg` list, at least two and at most
+/// `MAX_AVG_CITIES`.
+fn parse_avg_cities(args: &str) -> Result<Vec<String>, String> {
+    let mut cities: Vec<String> = Vec::new();
+    for city in args.split(',').map(input::clean_city) {
+        if !city.is_empty() && !cities.iter().any(|c| c.eq_ignore_ascii_case(&city)) {
+            cities.push(city);
+        }
+    }
+    match cities.len() {
+        0 | 1 => Err("Give at least two cities, separated by commas.".into()),
+        n if n > MAX_AVG_CITIES => {
+            Err(format!("At most {MAX_AVG_CITIES} cities at once, got {n}."))
+        }
+        _ => Ok(cities),
+    }
+}
+
+/// Mean and spread of the current AQI of `cities`, with the cleanest and dirtiest of them.
+/// Cities that couldn't be fetched are listed and left out of the numbers.
+fn render_average(
+    cities: &[String],
+    readings: &HashMap<String, Result<PollutionData, String>>,
+    settings: &UserSettings,
+) -> String {
+    let mut rows: Vec<(&str, u32)> = Vec::new();
+    let mut failed: Vec<&str> = Vec::new();
+    for city in cities {
+        let aqi = match readings.get(&city.to_lowercase()) {
+            Some(Ok(data)) => {
+                current_aqi(data).map(|aqi_level| (data.city.name.as_str(), aqi_level.aqi()))
+            }
+            Some(Err(e)) => Err(e.clone()),
+            None => Err("not fetched".to_owned()),
+        };
+        match aqi {
+            Ok(row) => rows.push(row),
+            Err(e) => {
+                tracing::warn!("{city}: {e}");
+                failed.push(city);
+            }
+        }
+    }
+    let failed = if failed.is_empty() {
+        String::new()
+    } else {
+        format!("\nCouldn't get data for: {}", failed.join(", "))
+    };
+    if rows.is_empty() {
+        return format!("Couldn't get data for any of these cities.{failed}");
+    }
+    rows.sort_by_key(|(_, aqi)| *aqi);
+    if let [(name, aqi)] = rows[..] {
+        return format!("Only {name} could be fetched, at AQI {aqi}.{failed}");
+    }
+
+    let count = rows.len() as f64;
+    let mean = rows.iter().map(|(_, aqi)| f64::from(*aqi)).sum::<f64>() / count;
+    let deviation = (rows
+        .iter()
+        .map(|(_, aqi)| (f64::from(*aqi) - mean).powi(2))
+        .sum::<f64>()
+        / count)
+        .sqrt();
+    let mean_aqi = mean.round() as u32;
+    let mean_level = AirQualityLevel::try_from(mean_aqi).unwrap_or(AirQualityLevel::Hazardous);
+    let level = level_from_aqi(&AirQuality::new(mean_aqi, mean_level), settings);
+    let (emoji, _) = air_quality_to_emoji(level, mean_aqi);
+    let (cleanest, lowest) = rows[0];
+    let (dirtiest, highest) = rows[rows.len() - 1];
+
+    format!(
+        "Average of {} cities: {emoji} {level} (AQI {mean_aqi})\n\
+         Spread: AQI {lowest} to {highest}, standard deviation {deviation:.0}\n\
+         Cleanest: {cleanest} (AQI {lowest})\n\
+         Dirtiest: {dirtiest} (AQI {highest}){failed}",
+        rows.len()
+    )
+}
+
+/// Renders the current reading of `city` as a card to share, as a PNG.
+async fn get_city_card(
+    waqi: &Waqi,
+    city: &str,
+    settings: &UserSettings,
+    attribution: &str,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let data = waqi.feed(city).await?;
+
+    let aqi_level = current_aqi(&data)?;
+    let level = level_from_aqi(&aqi_level, settings);
+    // The station's own clock, without the seconds, is what people there would expect.
+    let time = match NaiveDateTime::parse_from_str(&data.time.s, "%Y-%m-%d %H:%M:%S") {
+        Ok(time) => time
+            .format(&format!("%Y-%m-%d {}", settings.time_format.pattern()))
+            .to_string(),
+        Err(_) => data.time.s.clone(),
+    };
+    let observed = format!("Measured {time} (UTC{})", data.time.tz);
+    Ok(chart::reading_card(
+        &data.city.name,
+        aqi_level,
+        level,
+        &observed,
+        attribution,
+    )?)
+}
+
+/// AQI of the dominant pollutant, or the overall AQI for stations that publish nothing else.
+fn current_aqi(data: &PollutionData) -> Result<AirQuality, String> {
+    let dominant = data.dominentpol.as_str();
+
+    // Some stations only publish the overall AQI, without any per-pollutant values.
+    if data.iaqi.is_empty() {
+        return Ok(air_quality(data.aqi));
+    }
+
+    let sub_index = data
+        .iaqi
+        .get(dominant)
+        .ok_or_else(|| format!("Data for dominant pollutant ({dominant}) not available."))?
+        .v;
+
+    sub_index_aqi(dominant, sub_index)
+        .map_err(|e| format!("Failed to determine AQI from {dominant}: {e}"))
+}
+
+fn render_report(
+    data: &PollutionData,
+    settings: &UserSettings,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let dominant = data.dominentpol.as_str();
+    let overall_only = data.iaqi.is_empty();
+    let aqi_level = current_aqi(data)?;
+
+    tracing::info!(
+        "City: {}, Dominant pol: {dominant}, overall only: {overall_only}, => {aqi_level:?}",
+        data.city.name
+    );
+
+    // The headline and its bar follow the user's focus pollutant when the station reports it.
+    let focus = focused_pollutant(data, settings);
+    let headline = focus.map_or(aqi_level, |(_, focus_level)| focus_level);
+    let headline_level = level_from_aqi(&headline, settings);
+    let (emoji, progress_bar) = air_quality_to_emoji(headline_level, headline.aqi());
+    let emoji = format!("{emoji}{}", band_marker(&headline, settings));
+    let focus_label = focus.map_or(String::new(), |(name, _)| format!(" ({name})"));
+    let headline_pollutant = focus.map_or(dominant, |(name, _)| name);
+    let concentration = match settings.value_mode {
+        ValueMode::Aqi => None,
+        ValueMode::Concentration => headline_concentration(data, headline_pollutant),
+    };
+
+    let stale = stale_warning(data, settings);
+
+    if settings.verbosity == Verbosity::Brief {
+        let stale = stale.map_or(String::new(), |warning| format!("\n{warning}"));
+        let value = concentration.unwrap_or_else(|| format!("AQI {}", headline.aqi()));
+        return Ok(format!(
+            "{}: {} {} ({value}){focus_label}{stale}",
+            data.city.name, emoji, headline_level
+        ));
+    }
+
+    let current_date = current_date(data)?;
+    let value = concentration.map_or(String::new(), |value| format!(" {value}"));
+
+    let mut text = if settings.show_ladder {
+        format!("{}\n", render_ladder())
+    } else {
+        String::new()
+    };
+    text.push_str(&format!(
+        "{}\n{} {}{value}{focus_label}\n{}\n",
+        data.city.name, current_date, emoji, progress_bar
+    ));
+
+    if let Some(warning) = stale {
+        text.push_str(&warning);
+        text.push('\n');
+    }
+
+    if overall_only {
+        text.push_str("ℹ️ Only overall AQI available for this station.\n");
+    }
+
+    if settings.show_cigarettes {
+        if let Some(line) = cigarettes_line(data) {
+            text.push_str(&line);
+        }
+    }
+
+    text.push_str(&who_lines(data));
+
+    let forecast = if settings.include_forecast_in_wis {
+        forecast_days(data)?
+    } else {
+        Vec::new()
+    };
+    for (day, forecast_aqi_level) in forecast.into_iter().take(settings.forecast_limit()) {
+        let (emoji, progress_bar) = air_quality_to_emoji(
+            level_from_aqi(&forecast_aqi_level, settings),
+            forecast_aqi_level.aqi(),
+        );
+        text.push_str(&format!("{} {}\n{}\n", day, emoji, progress_bar));
+    }
+
+    if settings.verbosity == Verbosity::Detailed && !overall_only {
+        text.push_str(&render_details(data, settings));
+    }
+
+    // WAQI asks for its sources to be credited, in full only where there's room for it.
+    match (settings.verbosity, data.attributions.len()) {
+        (_, 0) => {}
+        (Verbosity::Detailed, _) => text.push_str(&render_sources(data)),
+        (_, 1) => text.push_str("\nSource: 1 agency (/sources city_name)\n"),
+        (_, agencies) => text.push_str(&format!(
+            "\nSource: {agencies} agencies (/sources city_name)\n"
+        )),
+    }
+
+    Ok(text)
 }
 
-#[allow(unused)]
-#[derive(Debug, Deserialize)]
-struct PollutionData {
-    aqi: u32,
-    idx: u32,
-    attributions: Vec<Attribution>,
-    city: City,
-    dominentpol: String,
-    iaqi: HashMap<String, IaqiValue>,
-    time: Time,
-    forecast: Forecast,
+/// Every agency the station's data comes from, with a link to it.
+fn render_sources(data: &PollutionData) -> String {
+    let mut text = String::from("\nSources:\n");
+    for attribution in &data.attributions {
+        text.push_str(&format!("{}: {}\n", attribution.name, attribution.url));
+    }
+    text
 }
 
-#[allow(unused)]
-#[derive(Debug, Deserialize)]
-struct Attribution {
-    url: String,
-    name: String,
+/// "≈ N cigarettes" line derived from the station's PM2.5, if it reports any.
+fn cigarettes_line(data: &PollutionData) -> Option<String> {
+    let pm25 = concentration::pm25(data.iaqi.get("pm25")?.v)?;
+    let cigarettes = concentration::cigarettes(pm25);
+
+    Some(format!(
+        "🚬 ≈ {cigarettes:.1} cigarettes/day (rough approximation from PM2.5 {pm25:.0} µg/m³)\n"
+    ))
 }
 
-#[allow(unused)]
-#[derive(Debug, Deserialize)]
-struct City {
-    geo: Vec<f64>,
-    name: String,
-    url: String,
-    location: String,
+/// How the current PM concentrations compare to the WHO 24-hour guidelines.
+fn who_lines(data: &PollutionData) -> String {
+    let mut text = String::new();
+    for (key, name, _) in concentration::WHO_24H_GUIDELINES {
+        let multiple = data
+            .iaqi
+            .get(key)
+            .and_then(|value| concentration::ug_m3(key, value.v))
+            .and_then(|concentration| concentration::who_multiple(key, concentration));
+        match multiple {
+            Some(multiple) if multiple > 1.0 => text.push_str(&format!(
+                "🩺 {name} is {multiple:.1}× the WHO 24h guideline\n"
+            )),
+            Some(_) => text.push_str(&format!("🩺 {name} is within the WHO 24h guideline\n")),
+            None => {}
+        }
+    }
+    text
 }
 
-#[allow(unused)]
-#[derive(Debug, Deserialize)]
-struct IaqiValue {
-    v: f64,
+/// The concentration behind `pollutant`'s sub-index, e.g. "PM2.5: 42 µg/m³".
+fn headline_concentration(data: &PollutionData, pollutant: &str) -> Option<String> {
+    let sub_index = data.iaqi.get(pollutant)?.v;
+    let concentration = concentration::ug_m3(&normalize_pollutant(pollutant), sub_index)?;
+    Some(format!(
+        "{}: {concentration:.0} µg/m³",
+        pollutant_name(pollutant)
+    ))
 }
 
-#[allow(unused)]
-#[derive(Debug, Deserialize)]
-struct Time {
-    s: String,
-    tz: String,
-    v: u64,
-    iso: String,
+/// How a pollutant key is usually written.
+fn pollutant_name(pollutant: &str) -> String {
+    match normalize_pollutant(pollutant).as_str() {
+        "pm25" => "PM2.5".to_owned(),
+        other => other.to_uppercase(),
+    }
 }
 
-#[allow(unused)]
-#[derive(Debug, Deserialize)]
-struct Forecast {
-    daily: HashMap<String, Vec<DailyForecast>>,
+/// The user's focus pollutant and its AQI, if they set one and this station reports it.
+fn focused_pollutant<'a>(
+    data: &PollutionData,
+    settings: &'a UserSettings,
+) -> Option<(&'a str, AirQuality)> {
+    let name = settings.focus_pollutant.as_deref()?;
+    let value = data.iaqi.get(name)?.v;
+    sub_index_aqi(name, value)
+        .ok()
+        .map(|aqi_level| (name, aqi_level))
 }
 
-#[allow(unused)]
-#[derive(Debug, Deserialize)]
-struct DailyForecast {
-    avg: u32,
-    day: String,
-    max: u32,
-    min: u32,
+/// Readings claiming to be older than this (2000-01-01) have a placeholder rather than a time.
+const EARLIEST_READING: i64 = 946_684_800;
+
+/// When the reading was taken. WAQI's `time.v` is the station's wall-clock time encoded as
+/// if it were UTC, so the station's offset has to be taken back out. Timestamps too far off
+/// to be a real reading, like 0, are unknown.
+fn observed_at(time: &Time) -> Option<DateTime<Utc>> {
+    let offset: FixedOffset = time.tz.parse().ok()?;
+    let local = i64::try_from(time.v).ok()?;
+    let utc = local.checked_sub(i64::from(offset.local_minus_utc()))?;
+    if utc < EARLIEST_READING {
+        return None;
+    }
+    DateTime::from_timestamp(utc, 0)
 }
 
-// ------------------- //
-// BEGIN Bot Commands  //
-// ------------------- //
+/// How long ago the reading was taken, `None` if its time is unknown or in the future.
+fn reading_age(data: &PollutionData) -> Option<Duration> {
+    Utc::now()
+        .signed_duration_since(observed_at(&data.time)?)
+        .to_std()
+        .ok()
+}
 
-#[tokio::main]
-async fn main() {
-    let bot = Bot::from_env();
+/// Human-friendly age rounded to minutes, e.g. "5h 12m".
+fn format_age(age: Duration) -> String {
+    humantime::format_duration(Duration::from_secs(age.as_secs() / 60 * 60)).to_string()
+}
 
-    Command::repl(bot, answer).await;
+fn stale_warning(data: &PollutionData, settings: &UserSettings) -> Option<String> {
+    if settings.stale_after_secs == 0 {
+        return None;
+    }
+    let age = reading_age(data)?;
+    (age.as_secs() > settings.stale_after_secs)
+        .then(|| format!("⚠️ Last updated {} ago", format_age(age)))
 }
 
-#[derive(BotCommands, Clone)]
-#[command(
-    rename_rule = "lowercase",
-    description = "These commands are supported:"
-)]
-enum Command {
-    #[command(description = "start the bot.")]
-    Start,
-    #[command(description = "display this text.")]
-    Help,
-    #[command(description = "get pollution data for a city.")]
-    Wis { city: String },
+/// The station's local date of the reading, from `time.iso` or, if that's missing or
+/// malformed, the start of `time.s`.
+fn current_date(data: &PollutionData) -> Result<NaiveDate, Box<dyn std::error::Error>> {
+    if let Ok(observed) = DateTime::parse_from_rfc3339(&data.time.iso) {
+        return Ok(observed.date_naive());
+    }
+    let date = data
+        .time
+        .s
+        .split_whitespace()
+        .next()
+        .ok_or("Failed to parse date")?;
+    Ok(NaiveDate::parse_from_str(date, "%Y-%m-%d")?)
 }
 
-async fn answer(bot: Bot, msg: Message, cmd: Command) -> ResponseResult<()> {
-    match cmd {
-        Command::Help | Command::Start => {
-            bot.send_message(msg.chat.id, Command::descriptions().to_string())
-                .await?
-        }
-        Command::Wis { city } => {
-            if city.trim().is_empty() {
-                bot.send_message(msg.chat.id, "Usage:\n/wis city_name")
-                    .await?;
-                return Ok(());
-            }
-            let result = match get_city_pollution_emoji(city.as_str()).await {
-                Ok(text) => text,
-                Err(e) => {
-                    println!("{e}");
-                    format!("Couldn't get data for {city}")
-                }
-            };
-            bot.send_message(msg.chat.id, result).await?
-        }
+/// AQI of the dominant pollutant for each forecast day after the reading's date.
+fn forecast_days(
+    data: &PollutionData,
+) -> Result<Vec<(String, AirQuality)>, Box<dyn std::error::Error>> {
+    forecast_days_for(data, &data.dominentpol)
+}
+
+/// AQI of `pollutant` for each forecast day after the reading's date.
+fn forecast_days_for(
+    data: &PollutionData,
+    pollutant: &str,
+) -> Result<Vec<(String, AirQuality)>, Box<dyn std::error::Error>> {
+    // Forecast days are `YYYY-MM-DD` too, so they compare in date order.
+    let current_date = current_date(data)?.to_string();
+
+    let Some(forecast_list) = data.forecast.daily_for(pollutant) else {
+        return Ok(Vec::new());
     };
 
-    Ok(())
+    let mut days = Vec::new();
+    for day in forecast_list {
+        if day.day > current_date {
+            let forecast_val = day.avg as f64;
+
+            let forecast_aqi_level = sub_index_aqi(pollutant, forecast_val)
+                .map_err(|e| format!("Forecast AQI calc failed for {pollutant}: {e}"))?;
+
+            days.push((day.day.clone(), forecast_aqi_level));
+        }
+    }
+
+    Ok(days)
 }
 
-// --------------------- //
-// BEGIN Helper Functions//
-// --------------------- //
+/// `/preview`: the same reading rendered with every verbosity, then with the other value mode,
+/// each labelled with the command that picks it.
+fn render_previews(
+    data: &PollutionData,
+    settings: &UserSettings,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut previews = Vec::new();
+    for verbosity in [Verbosity::Brief, Verbosity::Normal, Verbosity::Detailed] {
+        let mut preview = settings.clone();
+        preview.verbosity = verbosity;
+        previews.push(format!(
+            "/verbosity {verbosity}\n\n{}",
+            render_report(data, &preview)?
+        ));
+    }
 
-async fn get_city_pollution_emoji(city: &str) -> Result<String, Box<dyn std::error::Error>> {
-    let data = get_city_pollution(city).await?;
+    let mut preview = settings.clone();
+    preview.value_mode = match settings.value_mode {
+        ValueMode::Aqi => ValueMode::Concentration,
+        ValueMode::Concentration => ValueMode::Aqi,
+    };
+    previews.push(format!(
+        "/valuemode {} (with your verbosity)\n\n{}",
+        preview.value_mode,
+        render_report(data, &preview)?
+    ));
+    Ok(previews)
+}
 
-    let dominant = data.dominentpol.as_str();
+/// `/coverage`: which supported pollutants the station reports, and whether it has weather
+/// conditions and a forecast.
+fn render_coverage(data: &PollutionData) -> String {
+    let mark = |present: bool| if present { "✅" } else { "❌" };
 
-    let val = data
-        .iaqi
-        .get(dominant)
-        .ok_or_else(|| format!("Data for dominant pollutant ({dominant}) not available."))?
-        .v;
+    let mut text = format!("{} reports:\n", data.city.name);
+    for pollutant in SUPPORTED_POLLUTANTS {
+        text.push_str(&format!(
+            "{} {}\n",
+            mark(data.iaqi.contains_key(pollutant)),
+            pollutant_name(pollutant)
+        ));
+    }
+    let conditions = CONDITIONS
+        .iter()
+        .any(|(key, _, _)| data.iaqi.contains_key(*key));
+    text.push_str(&format!("{} Weather conditions\n", mark(conditions)));
+    let forecast = data.forecast.daily.values().any(|days| !days.is_empty());
+    text.push_str(&format!("{} Forecast", mark(forecast)));
+    text
+}
 
-    let aqi_level = calc_aqi_by_name(dominant, val)
-        .map_err(|e| format!("Failed to determine AQI from {dominant}: {e}"))?;
+/// `/calendar`: one aligned row per forecast day with its weekday, AQI and emoji, as HTML.
+fn render_calendar(
+    data: &PollutionData,
+    settings: &UserSettings,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let city = html::escape(&data.city.name);
+    let days = forecast_days(data)?;
+    if days.is_empty() {
+        return Ok(format!("{city} has no forecast."));
+    }
 
-    println!("City: {city}, Dominant pol: {dominant}, value: {val}, => {aqi_level:?}");
+    let mut table = String::new();
+    for (day, aqi_level) in days.into_iter().take(settings.forecast_limit()) {
+        let label = match NaiveDate::parse_from_str(&day, "%Y-%m-%d") {
+            Ok(date) => date.format("%a %d %b").to_string(),
+            Err(_) => day,
+        };
+        // The emoji goes last, its width varies between clients.
+        let (emoji, _) =
+            air_quality_to_emoji(level_from_aqi(&aqi_level, settings), aqi_level.aqi());
+        table.push_str(&format!(
+            "{label:<10}  AQI {:>3}  {emoji}\n",
+            aqi_level.aqi()
+        ));
+    }
 
-    let current_date = data
-        .time
-        .s
-        .split_whitespace()
-        .next()
-        .ok_or("Failed to parse date")?;
+    Ok(format!("{city}\n<pre>{}</pre>", html::escape(&table)))
+}
 
-    let (emoji, progress_bar) = air_quality_to_emoji(aqi_level.level(), aqi_level.aqi());
-    let mut text = format!(
-        "💚➔ 💛➔ 🧡➔ ❤️➔ 💜➔ 🖤\n{}\n{} {}\n{}\n",
-        data.city.name, current_date, emoji, progress_bar
-    );
+/// `/plan`: the current reading next to tomorrow's forecast, with a recommendation.
+fn render_plan(
+    data: &PollutionData,
+    settings: &UserSettings,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let now = current_aqi(data)?;
+    let now_level = level_from_aqi(&now, settings);
+    let (now_emoji, _) = air_quality_to_emoji(now_level, now.aqi());
+
+    let tomorrow_date = current_date(data)?.succ_opt().map(|date| date.to_string());
+    let tomorrow = forecast_days(data)?
+        .into_iter()
+        .find(|(day, _)| Some(day) == tomorrow_date.as_ref());
+    let Some((_, tomorrow)) = tomorrow else {
+        return Ok(format!(
+            "{}\nNow: {now_emoji} AQI {} ({now_level})\nThere's no forecast for tomorrow yet.",
+            data.city.name,
+            now.aqi()
+        ));
+    };
+    let tomorrow_level = level_from_aqi(&tomorrow, settings);
+    let (tomorrow_emoji, _) = air_quality_to_emoji(tomorrow_level, tomorrow.aqi());
+
+    let recommendation = if now_level == tomorrow_level {
+        if now_level == AirQualityLevel::Good {
+            "Both days look good, go out whenever you like."
+        } else {
+            "About the same both days."
+        }
+    } else if (tomorrow_level as u8) < (now_level as u8) {
+        "Tomorrow looks cleaner — save your run for then."
+    } else {
+        "Today is the better day to be outside."
+    };
 
-    if let Some(forecast_list) = data.forecast.daily.get(dominant) {
-        for day in forecast_list {
-            if day.day.as_str() > current_date {
-                let forecast_val = day.avg as f64;
+    Ok(format!(
+        "{}\n\
+         Now │ Tomorrow\n\
+         {now_emoji} AQI {} │ {tomorrow_emoji} AQI {}\n\
+         {now_level} │ {tomorrow_level}\n\n\
+         {recommendation}",
+        data.city.name,
+        now.aqi(),
+        tomorrow.aqi()
+    ))
+}
 
-                let forecast_aqi_level = calc_aqi_by_name(dominant, forecast_val)
-                    .map_err(|e| format!("Forecast AQI calc failed for {dominant}: {e}"))?;
+/// Every pollutant's forecast, followed by the UV index when the station has one.
+fn render_forecast(
+    data: &PollutionData,
+    settings: &UserSettings,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let limit = settings.forecast_limit();
+    let mut text = format!("{} forecast\n", data.city.name);
 
-                let (emoji, progress_bar) =
-                    air_quality_to_emoji(forecast_aqi_level.level(), forecast_aqi_level.aqi());
-                text.push_str(&format!("{} {}\n{}\n", day.day, emoji, progress_bar));
-            }
+    let mut any = false;
+    for pollutant in SUPPORTED_POLLUTANTS {
+        let days = forecast_days_for(data, pollutant)?;
+        if days.is_empty() {
+            continue;
+        }
+        any = true;
+        text.push_str(&format!("\n{pollutant}:\n"));
+        for (day, aqi_level) in days.into_iter().take(limit) {
+            let (emoji, _) =
+                air_quality_to_emoji(level_from_aqi(&aqi_level, settings), aqi_level.aqi());
+            text.push_str(&format!("{day} {emoji} {}\n", aqi_level.aqi()));
+        }
+    }
+
+    // UV isn't a pollutant, so it gets its own scale instead of an AQI.
+    let current_date = current_date(data)?.to_string();
+    let uv_days: Vec<_> = data
+        .forecast
+        .daily_for("uvi")
+        .unwrap_or_default()
+        .iter()
+        .filter(|day| day.day > current_date)
+        .take(limit)
+        .collect();
+    if !uv_days.is_empty() {
+        any = true;
+        text.push_str("\nUV index (daily peak):\n");
+        for day in uv_days {
+            let (emoji, category, note) = uv_category(day.max);
+            text.push_str(&format!(
+                "{} {emoji} {} {category}: {note}\n",
+                day.day, day.max
+            ));
         }
     }
 
+    if !any {
+        text.push_str("\nNo forecast available.");
+    }
+
     Ok(text)
 }
 
-async fn get_city_pollution(city: &str) -> Result<PollutionData, Box<dyn std::error::Error>> {
-    let aqi_token = std::env::var("AQI_TOKEN").expect("AQI_TOKEN must be set!");
+/// WHO UV index category of `uvi`, with an emoji and what to do about it.
+fn uv_category(uvi: u32) -> (&'static str, &'static str, &'static str) {
+    match uvi {
+        0..=2 => ("🌥️", "Low", "no protection needed"),
+        3..=5 => ("🌤️", "Moderate", "seek shade around midday"),
+        6..=7 => ("☀️", "High", "cover up and use sunscreen"),
+        8..=10 => ("🔆", "Very high", "avoid the sun around midday"),
+        _ => ("⛱️", "Extreme", "stay in the shade"),
+    }
+}
 
-    let url = format!("https://api.waqi.info/feed/{city}/?token={aqi_token}");
-    let result = timeout(Duration::from_secs(10), reqwest::get(url)).await;
+/// Lists every pollutant the station reports, followed by the weather conditions if wanted.
+fn render_details(data: &PollutionData, settings: &UserSettings) -> String {
+    let mut text = String::from("\nPollutants:\n");
+    for (name, aqi_level) in compute_all(&data.iaqi) {
+        let (emoji, _) =
+            air_quality_to_emoji(level_from_aqi(&aqi_level, settings), aqi_level.aqi());
+        text.push_str(&format!("{name}: {emoji} {}\n", aqi_level.aqi()));
+    }
 
-    match result {
-        Ok(Ok(response)) => {
-            let resp = response.json::<ApiResponse>().await?;
-            if resp.status == "ok" {
-                Ok(resp.data)
-            } else {
-                Err(format!("API returned an error: {}", resp.status).into())
-            }
+    if !settings.show_conditions {
+        return text;
+    }
+
+    let conditions: Vec<String> = CONDITIONS
+        .iter()
+        .filter_map(|(key, label, unit)| {
+            data.iaqi
+                .get(*key)
+                .map(|value| format!("{label}: {}{unit}", value.v))
+        })
+        .collect();
+    if !conditions.is_empty() {
+        text.push_str("\nConditions:\n");
+        text.push_str(&conditions.join("\n"));
+        text.push('\n');
+    }
+
+    text
+}
+
+/// Meteorological `iaqi` keys reported by WAQI, with a label and unit for display.
+const CONDITIONS: [(&str, &str, &str); 6] = [
+    ("t", "🌡️ Temperature", "°C"),
+    ("h", "💧 Humidity", "%"),
+    ("w", "💨 Wind", " m/s"),
+    ("wg", "🌬️ Wind gust", " m/s"),
+    ("p", "⏲️ Pressure", " hPa"),
+    ("dew", "🌫️ Dew point", "°C"),
+];
+
+/// Extracts coordinates from either a bare `lat,lon` pair or a Google Maps link
+/// (`.../@lat,lon,15z` or `...?q=lat,lon`).
+fn parse_coordinates(input: &str) -> Option<(f64, f64)> {
+    let input = input.trim().replace("%2C", ",").replace("%2c", ",");
+
+    if let Some(coords) = parse_lat_lon(&input) {
+        return Some(coords);
+    }
+
+    if let Some((_, rest)) = input.split_once('@') {
+        if let Some(coords) = parse_lat_lon(rest) {
+            return Some(coords);
         }
-        Ok(Err(e)) => Err(Box::new(e)),            // reqwest error
-        Err(_) => Err("Request timed out".into()), // Timeout error
     }
+
+    let (_, query) = input.split_once('?')?;
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .filter(|(key, _)| matches!(*key, "q" | "query" | "ll"))
+        .find_map(|(_, value)| parse_lat_lon(value))
 }
 
-fn air_quality_to_emoji(level: AirQualityLevel, aqi: u32) -> (String, String) {
-    use AirQualityLevel::*;
+/// Parses a leading `lat,lon` pair, ignoring anything after it (e.g. a `,15z` zoom level).
+fn parse_lat_lon(text: &str) -> Option<(f64, f64)> {
+    let end = text
+        .find(|c: char| !(c.is_ascii_digit() || matches!(c, '.' | ',' | '-' | '+' | ' ')))
+        .unwrap_or(text.len());
+    let mut parts = text[..end].split(',').map(str::trim);
 
+    let lat: f64 = parts.next()?.parse().ok()?;
+    let lon: f64 = parts.next()?.parse().ok()?;
+
+    ((-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lon)).then_some((lat, lon))
+}
+
+fn air_quality_to_emoji(level: AirQualityLevel, aqi: u32) -> (String, String) {
     let progress_bar_size = 10;
     let progress = ((aqi.min(500) as f64) / 25.0).ceil() as usize;
     let progress = progress.min(progress_bar_size);
+    // The filler below counts down from the bar size, so it can never be outgrown.
+    debug_assert!(progress <= progress_bar_size);
     let progress_bar: String = "█".repeat(progress) + &"░".repeat(progress_bar_size - progress);
     let progress_bar = format!("{} [{}] {}", "🌳", progress_bar, "💀");
 
-    let emoji = match level {
+    (band_emoji(level).into(), progress_bar)
+}
+
+fn band_emoji(level: AirQualityLevel) -> &'static str {
+    use AirQualityLevel::*;
+
+    match level {
         Good => "💚",
         Moderate => "💛",
         UnhealthySensitive => "🧡",
         Unhealthy => "❤️",
         VeryUnhealthy => "💜",
         Hazardous => "🖤",
+    }
+}
+
+/// Every band's emoji from Good to Hazardous, e.g. `💚➔ 💛➔ ... 🖤`, drawn from the same
+/// glyphs as the readings so the two never disagree.
+fn render_ladder() -> String {
+    use AirQualityLevel::*;
+
+    [
+        Good,
+        Moderate,
+        UnhealthySensitive,
+        Unhealthy,
+        VeryUnhealthy,
+        Hazardous,
+    ]
+    .map(band_emoji)
+    .join("➔ ")
+}
+
+/// Cities `/random` picks from.
+const WORLD_CITIES: [&str; 40] = [
+    "Tokyo",
+    "Delhi",
+    "Shanghai",
+    "Beijing",
+    "Mumbai",
+    "Kolkata",
+    "Dhaka",
+    "Karachi",
+    "Lahore",
+    "Bangkok",
+    "Jakarta",
+    "Manila",
+    "Seoul",
+    "Singapore",
+    "Hong Kong",
+    "Taipei",
+    "Hanoi",
+    "Tehran",
+    "Istanbul",
+    "Cairo",
+    "Lagos",
+    "Nairobi",
+    "Johannesburg",
+    "Moscow",
+    "London",
+    "Paris",
+    "Berlin",
+    "Madrid",
+    "Rome",
+    "Warsaw",
+    "New York",
+    "Los Angeles",
+    "Chicago",
+    "Toronto",
+    "Mexico City",
+    "Bogota",
+    "Lima",
+    "Santiago",
+    "Sao Paulo",
+    "Sydney",
+];
+
+/// Picks of `/random` tried before giving up.
+const RANDOM_ATTEMPTS: usize = 3;
+
+/// Capitals `/world` ranks, picked to span the continents and the range of air quality.
+const WORLD_CAPITALS: [&str; 10] = [
+    "Beijing",
+    "New Delhi",
+    "Tokyo",
+    "Bangkok",
+    "Tehran",
+    "Moscow",
+    "London",
+    "Paris",
+    "Washington",
+    "Mexico City",
+];
+
+/// The single key of the `/world` cache.
+const WORLD_CACHE_KEY: &str = "world";
+
+/// Capitals on one page of `/world`.
+const WORLD_PAGE_SIZE: usize = 8;
+
+/// Callback data prefix of the `/world` page buttons, followed by the page to open.
+const WORLD_CALLBACK_PREFIX: &str = "world:";
+
+/// The `/world` readings, from the cache if they're recent enough.
+async fn world_readings(waqi: &Waqi, concurrency: usize) -> WorldReadings {
+    if let Some(readings) = WORLD_CACHE.get(WORLD_CACHE_KEY) {
+        return readings;
+    }
+    let readings = fetch_world(waqi, concurrency).await;
+    if readings.iter().any(|(_, reading)| reading.is_ok()) {
+        WORLD_CACHE.insert(WORLD_CACHE_KEY.to_owned(), readings.clone());
+    }
+    readings
+}
+
+/// Turns `/world` to the page of the tapped button, editing the message in place.
+async fn answer_world_page(
+    bot: Bot,
+    query: CallbackQuery,
+    store: Store,
+    waqi: Waqi,
+    shared_config: Arc<SharedConfig>,
+) -> ResponseResult<()> {
+    let page = query
+        .data
+        .as_deref()
+        .and_then(|data| pages::from_callback(data, WORLD_CALLBACK_PREFIX));
+    let (Some(page), Some(message)) = (page, &query.message) else {
+        bot.answer_callback_query(query.id).await?;
+        return Ok(());
+    };
+
+    let chat_id = message.chat().id;
+    let settings = store.settings(chat_id).await;
+    let bot = ChatBot::new(bot, &settings);
+    let concurrency = shared_config.current().scheduler_concurrency;
+    let readings = world_readings(&waqi, concurrency).await;
+    let (text, keyboard) = render_world(&readings, &settings, page);
+    let request = bot.edit_message_text(chat_id, message.id(), text);
+    match keyboard {
+        Some(keyboard) => request.reply_markup(keyboard).await?,
+        None => request.await?,
     };
+    bot.answer_callback_query(query.id).await?;
+    Ok(())
+}
+
+/// Fetches the current AQI of every one of `WORLD_CAPITALS`, `concurrency` at a time.
+async fn fetch_world(waqi: &Waqi, concurrency: usize) -> WorldReadings {
+    let permits = Arc::new(Semaphore::new(concurrency));
+    let targets = WORLD_CAPITALS
+        .iter()
+        .map(|city| (city.to_lowercase(), city.to_string()))
+        .collect();
+    let mut readings = scheduler::fetch_all(waqi, &permits, targets).await;
+    WORLD_CAPITALS
+        .iter()
+        .map(|city| {
+            let reading = match readings.remove(&city.to_lowercase()) {
+                Some(Ok(data)) => current_aqi(&data),
+                Some(Err(e)) => Err(e),
+                None => Err("not fetched".to_owned()),
+            };
+            (*city, reading)
+        })
+        .collect()
+}
+
+/// `page` (0-based) of the capitals ranked from the cleanest air to the dirtiest, the last page
+/// followed by the ones without data, along with the buttons to the other pages.
+fn render_world(
+    readings: &WorldReadings,
+    settings: &UserSettings,
+    page: usize,
+) -> (String, Option<InlineKeyboardMarkup>) {
+    let mut ranked: Vec<(&str, AirQuality)> = Vec::new();
+    let mut failed: Vec<&str> = Vec::new();
+    for (city, reading) in readings {
+        match reading {
+            Ok(aqi_level) => ranked.push((city, *aqi_level)),
+            Err(e) => {
+                tracing::warn!("{city}: {e}");
+                failed.push(city);
+            }
+        }
+    }
+    if ranked.is_empty() {
+        return (
+            "Couldn't get data for any capital, please try again.".to_owned(),
+            None,
+        );
+    }
+    ranked.sort_by_key(|(_, aqi_level)| aqi_level.aqi());
+
+    let page_count = pages::count(ranked.len(), WORLD_PAGE_SIZE);
+    // A button from before the cache refreshed may point past the last page.
+    let page = page.min(page_count - 1);
+    let mut text = String::from("🌍 World capitals, cleanest air first");
+    if page_count > 1 {
+        text.push_str(&format!(" (page {} of {page_count})", page + 1));
+    }
+    text.push_str(":\n");
+    let entries = ranked
+        .iter()
+        .enumerate()
+        .skip(page * WORLD_PAGE_SIZE)
+        .take(WORLD_PAGE_SIZE);
+    for (rank, (city, aqi_level)) in entries {
+        let level = level_from_aqi(aqi_level, settings);
+        let (emoji, _) = air_quality_to_emoji(level, aqi_level.aqi());
+        text.push_str(&format!(
+            "{}. {emoji} {city}: {level} (AQI {})\n",
+            rank + 1,
+            aqi_level.aqi()
+        ));
+    }
+    if !failed.is_empty() && page + 1 == page_count {
+        text.push_str(&format!("\nNo data right now: {}", failed.join(", ")));
+    }
+    (
+        text,
+        pages::keyboard(WORLD_CALLBACK_PREFIX, page, page_count),
+    )
+}
+
+/// A random number, good enough for picking a city or naming an error.
+fn random_u64() -> u64 {
+    use std::hash::{BuildHasher, Hasher};
+
+    // Every RandomState is seeded differently, which saves a dependency on `rand`.
+    std::collections::hash_map::RandomState::new()
+        .build_hasher()
+        .finish()
+}
+
+/// A random index below `len`, good enough for picking a city.
+fn random_index(len: usize) -> usize {
+    (random_u64() % len as u64) as usize
+}
+
+/// Logs `error` under a short random id and adds the id to `reply`, so a user quoting it in
+/// `/feedback` leads an operator to the matching log line.
+fn failure_reply(reply: String, error: impl std::fmt::Display) -> String {
+    let error_id = format!("{:06x}", random_u64() & 0xff_ffff);
+    tracing::warn!(error_id, "{error}");
+    format!("{reply} (error {error_id})")
+}
+
+/// Pollutant names understood by `sub_index_aqi`.
+const SUPPORTED_POLLUTANTS: [&str; 6] = ["pm25", "pm10", "o3", "no2", "so2", "co"];
+
+/// Most typos a pollutant name can have and still get a suggestion.
+const MAX_POLLUTANT_TYPOS: usize = 2;
+
+/// `input` as one of `SUPPORTED_POLLUTANTS`, aliases included. Otherwise the error holds the
+/// closest supported name, if one is within a couple of typos.
+fn parse_pollutant(input: &str) -> Result<&'static str, Option<&'static str>> {
+    let input = normalize_pollutant(input);
+    if let Some(name) = SUPPORTED_POLLUTANTS.iter().find(|name| **name == input) {
+        return Ok(name);
+    }
+
+    let candidates = SUPPORTED_POLLUTANTS
+        .iter()
+        .map(|name| (*name, *name))
+        .chain(POLLUTANT_ALIASES);
+    Err(candidates
+        .map(|(spelling, name)| (edit_distance(&input, spelling), name))
+        .filter(|(distance, _)| *distance <= MAX_POLLUTANT_TYPOS)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, name)| name))
+}
+
+/// Levenshtein distance between `a` and `b`, counted in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// An AQI with its US EPA band. Only readings beyond the top of the scale (500) have no band
+/// of their own, so they count as hazardous.
+fn air_quality(aqi: u32) -> AirQuality {
+    let level = AirQualityLevel::try_from(aqi).unwrap_or(AirQualityLevel::Hazardous);
+    AirQuality::new(aqi, level)
+}
+
+/// AQI of a WAQI sub-index of one of `SUPPORTED_POLLUTANTS`. WAQI reports `iaqi` and forecast
+/// values on the US EPA scale already, so the sub-index is the AQI and is never read as a
+/// concentration.
+fn sub_index_aqi(pollutant: &str, sub_index: f64) -> Result<AirQuality, String> {
+    let pollutant = normalize_pollutant(pollutant);
+    if !SUPPORTED_POLLUTANTS.contains(&pollutant.as_str()) {
+        return Err(format!("Unsupported or unknown pollutant: {pollutant}"));
+    }
+    if !sub_index.is_finite() || sub_index < 0.0 {
+        return Err(format!("Invalid sub-index for {pollutant}: {sub_index}"));
+    }
+    Ok(air_quality(sub_index.round() as u32))
+}
 
-    (emoji.into(), progress_bar)
+/// AQI of every pollutant in `iaqi` that `sub_index_aqi` understands, worst first. Ties
+/// are ordered by name so the result doesn't depend on the map's order.
+fn compute_all(iaqi: &HashMap<String, IaqiValue>) -> Vec<(String, AirQuality)> {
+    let mut pollutants: Vec<_> = iaqi
+        .iter()
+        .filter_map(|(name, value)| {
+            sub_index_aqi(name, value.v)
+                .ok()
+                .map(|aqi_level| (name.clone(), aqi_level))
+        })
+        .collect();
+    pollutants.sort_by(|(a_name, a), (b_name, b)| b.aqi().cmp(&a.aqi()).then(a_name.cmp(b_name)));
+    pollutants
 }
 
-fn calc_aqi_by_name(pollutant: &str, value: f64) -> Result<AirQuality, String> {
-    match pollutant.to_lowercase().as_str() {
-        "pm25" => pm2_5(value).map_err(|e| e.to_string()),
-        "pm10" => pm10(value).map_err(|e| e.to_string()),
-        "o3" => ozone8(value).map_err(|e| e.to_string()),
-        "no2" => no2(value).map_err(|e| e.to_string()),
-        "so2" => so2_1(value).map_err(|e| e.to_string()),
-        "co" => co(value).map_err(|e| e.to_string()),
-        other => Err(format!("Unsupported or unknown pollutant: {other}")),
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A WAQI feed response: PM2.5 dominant at 87, with a PM2.5 forecast and a UV one.
+    const FEED: &str = include_str!("../fuzz/corpus/parse_response/feed.json");
+
+    /// The reading in a feed response.
+    fn feed(body: &str) -> PollutionData {
+        match model::parse_envelope(body).unwrap() {
+            model::Envelope::Ok(data) => *data,
+            model::Envelope::Error(message) => panic!("WAQI error: {message}"),
+        }
+    }
+
+    /// `FEED` with its PM2.5 sub-index replaced.
+    fn feed_with_pm25(sub_index: &str) -> PollutionData {
+        feed(&FEED.replace(
+            r#""pm25":{"v":87}"#,
+            &format!(r#""pm25":{{"v":{sub_index}}}"#),
+        ))
+    }
+
+    #[test]
+    fn sub_index_is_the_aqi() {
+        let aqi_level = sub_index_aqi("pm25", 150.0).unwrap();
+        assert_eq!(aqi_level.aqi(), 150);
+        assert_eq!(aqi_level.level(), AirQualityLevel::UnhealthySensitive);
+
+        assert_eq!(sub_index_aqi("PM2.5", 42.4).unwrap().aqi(), 42);
+        assert_eq!(sub_index_aqi("o3", 120.0).unwrap().aqi(), 120);
+        assert_eq!(
+            sub_index_aqi("pm10", 650.0).unwrap().level(),
+            AirQualityLevel::Hazardous
+        );
+        assert!(sub_index_aqi("t", 18.0).is_err());
+        assert!(sub_index_aqi("pm25", -1.0).is_err());
+    }
+
+    #[test]
+    fn pollutants_table_labels_concentration_and_aqi_of_one_reading() {
+        let table = render_pollutants(&feed_with_pm25("150"), &UserSettings::default());
+        assert!(table.contains("55.4 µg/m³ AQI 150"), "{table}");
+        // Ozone's sub-index isn't mistaken for ppm, so it stays in the table.
+        assert!(table.contains("o3 "), "{table}");
+    }
+
+    #[test]
+    fn every_view_of_a_reading_agrees_on_its_aqi() {
+        let data = feed_with_pm25("150");
+        assert_eq!(current_aqi(&data).unwrap().aqi(), 150);
+        assert!(render_standards(&data).unwrap().contains("(AQI 150)"));
+
+        let settings = UserSettings {
+            focus_pollutant: Some("o3".to_owned()),
+            ..UserSettings::default()
+        };
+        let (_, focused) = focused_pollutant(&data, &settings).unwrap();
+        assert_eq!(focused.aqi(), 13);
+
+        let days = forecast_days_for(&data, "pm25").unwrap();
+        let days: Vec<_> = days
+            .iter()
+            .map(|(day, aqi_level)| (day.as_str(), aqi_level.aqi()))
+            .collect();
+        assert_eq!(days, [("2026-10-15", 96)]);
+    }
+
+    #[test]
+    fn band_position_with_the_largest_cutoff_does_not_overflow() {
+        let settings = UserSettings {
+            band_cutoffs: Some([1, 2, 3, 4, u32::MAX]),
+            ..UserSettings::default()
+        };
+        let position = band_position(&air_quality(1000), &settings);
+        assert!((0.0..=1.0).contains(&position));
+    }
+
+    #[test]
+    fn band_midpoints_get_the_half_marker() {
+        let standard = UserSettings {
+            band_markers: true,
+            ..UserSettings::default()
+        };
+        let custom = UserSettings {
+            band_cutoffs: Some([40, 80, 120, 160, 250]),
+            ..standard.clone()
+        };
+        // (settings, band start, band midpoint): Moderate on the standard scale, Moderate and
+        // Very Unhealthy on the custom one.
+        for (settings, start, midpoint) in
+            [(&standard, 51, 75), (&custom, 41, 60), (&custom, 161, 205)]
+        {
+            let midpoint = air_quality(midpoint);
+            assert!((band_position(&midpoint, settings) - 0.5).abs() < 1e-9);
+            assert_eq!(band_marker(&midpoint, settings), "🔸");
+
+            let start = air_quality(start);
+            assert!(band_position(&start, settings) < 0.1);
+            assert_eq!(band_marker(&start, settings), "");
+        }
+
+        // Without band markers even the top of a band gets the plain glyph.
+        assert_eq!(band_marker(&air_quality(100), &UserSettings::default()), "");
+    }
+
+    #[test]
+    fn band_cutoffs_above_the_plausible_aqi_are_rejected() {
+        assert_eq!(
+            parse_band_cutoffs("40 80 120 160 250"),
+            Ok([40, 80, 120, 160, 250])
+        );
+        assert_eq!(parse_band_cutoffs("1 2 3 4 1000"), Ok([1, 2, 3, 4, 1000]));
+        assert!(parse_band_cutoffs("1 2 3 4 1001").is_err());
+        assert!(parse_band_cutoffs("1 2 3 4 4294967295").is_err());
+    }
+
+    #[test]
+    fn band_cutoffs_must_be_five_increasing_positive_numbers() {
+        assert_eq!(
+            parse_band_cutoffs("40,80, 120 160  250"),
+            Ok([40, 80, 120, 160, 250])
+        );
+        assert!(parse_band_cutoffs("40 80 80 160 250").is_err());
+        assert!(parse_band_cutoffs("40 80 70 160 250").is_err());
+        assert!(parse_band_cutoffs("0 80 120 160 250").is_err());
+        assert!(parse_band_cutoffs("40 80 120 160").is_err());
+        assert!(parse_band_cutoffs("40 80 120 160 250 300").is_err());
+        assert!(parse_band_cutoffs("40 80 -120 160 250").is_err());
+    }
+
+    #[test]
+    fn custom_cutoffs_assign_the_bands() {
+        use AirQualityLevel::*;
+
+        let settings = UserSettings {
+            band_cutoffs: Some([40, 80, 120, 160, 250]),
+            ..UserSettings::default()
+        };
+        let bands = [0, 40, 41, 80, 81, 120, 121, 160, 161, 250, 251, 700]
+            .map(|aqi| level_from_aqi(&air_quality(aqi), &settings));
+        assert_eq!(
+            bands,
+            [
+                Good,
+                Good,
+                Moderate,
+                Moderate,
+                UnhealthySensitive,
+                UnhealthySensitive,
+                Unhealthy,
+                Unhealthy,
+                VeryUnhealthy,
+                VeryUnhealthy,
+                Hazardous,
+                Hazardous
+            ]
+        );
+        // Without custom cutoffs the standard bands apply.
+        assert_eq!(
+            level_from_aqi(&air_quality(45), &UserSettings::default()),
+            Good
+        );
+
+        // The other views follow the same cutoffs as `/wis`.
+        let table = render_pollutants(&feed(FEED), &settings);
+        assert!(
+            table.contains("AQI  87 🧡 Unhealthy for sensitive groups"),
+            "{table}"
+        );
+    }
+
+    #[test]
+    fn local_times_are_in_the_users_time_zone() {
+        let time = "2026-10-15T06:30:00Z".parse().unwrap();
+        let settings = UserSettings {
+            timezone: Some(chrono_tz::Asia::Tehran),
+            time_format: TimeFormat::H12,
+            ..UserSettings::default()
+        };
+        assert_eq!(format_local(time, &settings), "2026-10-15 10:00 AM");
+    }
+
+    #[test]
+    fn progress_bar_always_has_ten_segments() {
+        for aqi in 0..=1000 {
+            let (_, progress_bar) = air_quality_to_emoji(AirQualityLevel::Good, aqi);
+            let segments = progress_bar
+                .chars()
+                .filter(|c| matches!(c, '█' | '░'))
+                .count();
+            assert_eq!(segments, 10, "aqi {aqi}: {progress_bar}");
+        }
+    }
+
+    #[test]
+    fn cigarettes_line_only_shows_with_pm25() {
+        let line = cigarettes_line(&feed_with_pm25("150")).unwrap();
+        assert!(line.contains("≈ 2.5 cigarettes/day"), "{line}");
+        assert!(line.contains("PM2.5 55 µg/m³"), "{line}");
+
+        let without_pm25 = feed(&FEED.replace(r#""pm25":{"v":87},"#, ""));
+        assert_eq!(cigarettes_line(&without_pm25), None);
+    }
+
+    #[test]
+    fn uv_forecast_gets_its_own_block() {
+        let forecast = render_forecast(&feed(FEED), &UserSettings::default()).unwrap();
+        let (pollutants, uv) = forecast.split_once("UV index (daily peak):\n").unwrap();
+        assert!(!pollutants.contains("uvi"), "{forecast}");
+        assert_eq!(uv, "2026-10-15 🌤️ 5 Moderate: seek shade around midday\n");
+    }
+
+    #[test]
+    fn who_lines_cover_only_pollutants_with_a_guideline() {
+        assert_eq!(
+            who_lines(&feed_with_pm25("150")),
+            "🩺 PM2.5 is 3.7× the WHO 24h guideline\n\
+             🩺 PM10 is within the WHO 24h guideline\n"
+        );
+    }
+
+    #[test]
+    fn reading_date_prefers_iso_and_falls_back_to_s() {
+        let date = |data: &PollutionData| current_date(data).ok().map(|d| d.to_string());
+        let mut data = feed(FEED);
+        data.time.s = "2026-10-13 08:00:00".to_owned();
+        assert_eq!(date(&data).as_deref(), Some("2026-10-14"));
+
+        data.time.iso = "yesterday".to_owned();
+        assert_eq!(date(&data).as_deref(), Some("2026-10-13"));
+        data.time.iso = String::new();
+        assert_eq!(date(&data).as_deref(), Some("2026-10-13"));
+
+        data.time.s = "13/10/2026".to_owned();
+        assert_eq!(date(&data), None);
+        data.time.s = String::new();
+        assert_eq!(date(&data), None);
+    }
+
+    #[test]
+    fn station_without_iaqi_is_rendered_from_the_overall_aqi() {
+        let iaqi = FEED.find(r#""iaqi":"#).unwrap()..FEED.find(r#","time":"#).unwrap();
+        let mut body = FEED.to_owned();
+        body.replace_range(iaqi, r#""iaqi":{}"#);
+        let data = feed(&body);
+        assert!(data.iaqi.is_empty());
+
+        let settings = UserSettings {
+            verbosity: Verbosity::Detailed,
+            ..UserSettings::default()
+        };
+        let report = render_report(&data, &settings).unwrap();
+        assert!(report.contains("💛"), "{report}");
+        assert!(
+            report.contains("Only overall AQI available for this station."),
+            "{report}"
+        );
+        assert!(!report.contains("Pollutants:"), "{report}");
+    }
+
+    #[test]
+    fn zero_and_far_future_timestamps_are_unknown() {
+        let mut data = feed(FEED);
+        let observed = observed_at(&data.time).unwrap();
+        assert_eq!(observed.to_rfc3339(), "2026-10-14T08:30:00+00:00");
+
+        let settings = UserSettings {
+            stale_after_secs: 1,
+            ..UserSettings::default()
+        };
+        for v in [0, u64::MAX, i64::MAX as u64, 32_503_680_000] {
+            data.time.v = v;
+            assert_eq!(reading_age(&data), None, "time.v {v}");
+            assert_eq!(stale_warning(&data, &settings), None, "time.v {v}");
+        }
+    }
+
+    #[test]
+    fn pollutant_aliases_are_accepted_and_typos_get_a_suggestion() {
+        assert_eq!(parse_pollutant("pm2.5"), Ok("pm25"));
+        assert_eq!(parse_pollutant("PM2_5"), Ok("pm25"));
+        assert_eq!(parse_pollutant("ozone"), Ok("o3"));
+        assert_eq!(parse_pollutant("carbon monoxide"), Ok("co"));
+        assert_eq!(parse_pollutant("Sulphur dioxide"), Ok("so2"));
+
+        assert_eq!(parse_pollutant("pm24"), Err(Some("pm25")));
+        assert_eq!(parse_pollutant("ozon"), Err(Some("o3")));
+        assert_eq!(parse_pollutant("humidity"), Err(None));
+    }
+
+    #[test]
+    fn dotted_dominant_pollutant_gets_an_aqi() {
+        let data = feed(include_str!(
+            "../fuzz/corpus/parse_response/feed_dotted_pollutant.json"
+        ));
+        assert_eq!(current_aqi(&data).unwrap().aqi(), 87);
+    }
+
+    #[test]
+    fn ascii_report_snapshot() {
+        let settings = UserSettings {
+            ascii: true,
+            stale_after_secs: 0,
+            show_cigarettes: true,
+            ..UserSettings::default()
+        };
+        // Replies are converted on their way out, like `ChatBot` does.
+        let report = ascii::asciify(&render_report(&feed(FEED), &settings).unwrap());
+        assert!(report.is_ascii(), "{report}");
+        assert_eq!(
+            report,
+            "[GOOD] -> [MODERATE] -> [USG] -> [UNHEALTHY] -> [VERY UNHEALTHY] -> [HAZARDOUS]\n\
+             Tehran\n\
+             2026-10-14 [MODERATE]\n\
+             |####......|\n\
+             * ~ 1.3 cigarettes/day (rough approximation from PM2.5 29 ug/m3)\n\
+             * PM2.5 is 1.9x the WHO 24h guideline\n\
+             * PM10 is within the WHO 24h guideline\n\
+             2026-10-15 [MODERATE]\n\
+             |####......|\n\
+             \n\
+             Source: 1 agency (/sources city_name)\n"
+        );
+    }
+
+    #[test]
+    fn focus_pollutant_drives_the_bar_and_falls_back_to_the_dominant_one() {
+        let headline = |focus: &str| {
+            let settings = UserSettings {
+                focus_pollutant: Some(focus.to_owned()),
+                show_ladder: false,
+                include_forecast_in_wis: false,
+                ..UserSettings::default()
+            };
+            let report = render_report(&feed(FEED), &settings).unwrap();
+            report
+                .lines()
+                .skip(1)
+                .take(2)
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        assert_eq!(headline("o3"), "2026-10-14 💚 (o3)\n🌳 [█░░░░░░░░░] 💀");
+        // The station has no SO2, so the dominant PM2.5 is shown instead.
+        assert_eq!(headline("so2"), "2026-10-14 💛\n🌳 [████░░░░░░] 💀");
+    }
+
+    #[test]
+    fn reports_on_the_european_standard_get_a_caqi_line() {
+        let line = render_caqi(&feed(FEED)).unwrap();
+        assert!(line.starts_with("🇪🇺 EU CAQI: "), "{line}");
+        assert!(line.contains("(CAQI "), "{line}");
+        // The same grade `/standards` gives.
+        assert!(render_standards(&feed(FEED)).unwrap().contains(&line));
+    }
+
+    #[test]
+    fn ascii_covers_the_other_views_too() {
+        let data = feed_with_pm25("150");
+        let settings = UserSettings::default();
+        for text in [
+            render_standards(&data).unwrap(),
+            render_forecast(&data, &settings).unwrap(),
+            render_explanation(&data, &settings),
+        ] {
+            let ascii = ascii::asciify(&text);
+            assert!(ascii.is_ascii(), "{ascii}");
+        }
     }
 }
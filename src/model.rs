@@ -0,0 +1,355 @@
+//! WAQI API responses. Kept free of other crate dependencies so the fuzz target can
+//! include it directly.
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[allow(unused)]
+#[derive(Debug, Clone, Deserialize)]
+pub struct ApiResponse<T = PollutionData> {
+    pub status: String,
+    pub data: T,
+}
+
+/// Highest AQI a real reading can have. The scale ends at 500 but stations do report beyond it
+/// during extreme events.
+pub const MAX_PLAUSIBLE_AQI: u32 = 1000;
+
+#[allow(unused)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PollutionData {
+    pub aqi: u32,
+    pub idx: u32,
+    pub attributions: Vec<Attribution>,
+    pub city: City,
+    #[serde(deserialize_with = "deserialize_pollutant")]
+    pub dominentpol: String,
+    #[serde(deserialize_with = "deserialize_iaqi")]
+    pub iaqi: HashMap<String, IaqiValue>,
+    pub time: Time,
+    /// Some stations send no forecast at all.
+    #[serde(default)]
+    pub forecast: Forecast,
+}
+
+#[allow(unused)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Attribution {
+    pub url: String,
+    pub name: String,
+}
+
+#[allow(unused)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct City {
+    pub geo: Vec<f64>,
+    pub name: String,
+    pub url: String,
+    pub location: String,
+}
+
+impl PollutionData {
+    /// Whether the overall AQI is one a working station could report.
+    #[allow(unused)]
+    pub fn is_plausible(&self) -> bool {
+        self.aqi <= MAX_PLAUSIBLE_AQI
+    }
+}
+
+impl City {
+    /// The station's `(lat, lon)`, if `geo` holds exactly two finite numbers.
+    #[allow(unused)]
+    pub fn coords(&self) -> Option<(f64, f64)> {
+        match self.geo.as_slice() {
+            [lat, lon] if lat.is_finite() && lon.is_finite() => Some((*lat, *lon)),
+            _ => None,
+        }
+    }
+}
+
+#[allow(unused)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct IaqiValue {
+    pub v: f64,
+}
+
+#[allow(unused)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Time {
+    pub s: String,
+    pub tz: String,
+    pub v: u64,
+    /// ISO-8601 time with the station's offset. More reliable than `s`, but not always sent.
+    #[serde(default)]
+    pub iso: String,
+}
+
+#[allow(unused)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Forecast {
+    /// Only the well-formed days of every pollutant, see `deserialize_daily`.
+    #[serde(default, deserialize_with = "deserialize_daily")]
+    pub daily: HashMap<String, Vec<DailyForecast>>,
+}
+
+impl Forecast {
+    /// Daily forecast of `pollutant`, in any spelling `normalize_pollutant` understands.
+    pub fn daily_for(&self, pollutant: &str) -> Option<&[DailyForecast]> {
+        self.daily
+            .get(&normalize_pollutant(pollutant))
+            .map(Vec::as_slice)
+    }
+}
+
+#[allow(unused)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DailyForecast {
+    #[serde(deserialize_with = "deserialize_rounded")]
+    pub avg: u32,
+    pub day: String,
+    #[serde(deserialize_with = "deserialize_rounded")]
+    pub max: u32,
+    #[serde(deserialize_with = "deserialize_rounded")]
+    pub min: u32,
+}
+
+/// Spellings of pollutants, already compacted by `normalize_pollutant`, and the name WAQI
+/// usually gives them.
+pub const POLLUTANT_ALIASES: [(&str, &str); 5] = [
+    ("ozone", "o3"),
+    ("carbonmonoxide", "co"),
+    ("nitrogendioxide", "no2"),
+    ("sulfurdioxide", "so2"),
+    ("sulphurdioxide", "so2"),
+];
+
+/// The name a pollutant goes by in `iaqi` keys, e.g. "pm25" for "PM2.5", "pm2_5" or "Ozone"'s
+/// "o3". Every pollutant name, from WAQI or from users, is matched through this.
+pub fn normalize_pollutant(name: &str) -> String {
+    let compact: String = name
+        .chars()
+        .filter(|c| !matches!(c, '.' | '_' | '-') && !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect();
+    match POLLUTANT_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == compact)
+    {
+        Some((_, pollutant)) => (*pollutant).to_owned(),
+        None => compact,
+    }
+}
+
+fn deserialize_pollutant<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<String, D::Error> {
+    Ok(normalize_pollutant(&String::deserialize(deserializer)?))
+}
+
+fn deserialize_iaqi<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<String, IaqiValue>, D::Error> {
+    let raw = HashMap::<String, IaqiValue>::deserialize(deserializer)?;
+    Ok(raw
+        .into_iter()
+        .map(|(pollutant, value)| (normalize_pollutant(&pollutant), value))
+        .collect())
+}
+
+/// Accepts either an integer or a fractional number, rounding to the nearest `u32`.
+fn deserialize_rounded<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    let value = f64::deserialize(deserializer)?;
+    // `as` saturates, so negative values become 0 and huge ones u32::MAX.
+    Ok(value.round() as u32)
+}
+
+/// Reads `forecast.daily` leniently: keys that don't hold an array and days that don't parse
+/// are dropped instead of failing the whole response, so a station sending an odd extra entry
+/// still gets its reading shown.
+fn deserialize_daily<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<String, Vec<DailyForecast>>, D::Error> {
+    let raw = HashMap::<String, serde_json::Value>::deserialize(deserializer)?;
+    Ok(raw
+        .into_iter()
+        .filter_map(|(pollutant, days)| {
+            let serde_json::Value::Array(days) = days else {
+                return None;
+            };
+            let days: Vec<DailyForecast> = days
+                .into_iter()
+                .filter_map(|day| serde_json::from_value(day).ok())
+                .collect();
+            (!days.is_empty()).then_some((normalize_pollutant(&pollutant), days))
+        })
+        .collect())
+}
+
+/// One day of the `/feed/<target>/history/` endpoint, which only some token tiers may use.
+#[allow(unused)]
+#[derive(Debug, Clone, Deserialize)]
+pub struct HistoricalDay {
+    pub day: String,
+    #[serde(deserialize_with = "deserialize_rounded")]
+    pub aqi: u32,
+    #[serde(default, deserialize_with = "deserialize_pollutant")]
+    pub dominentpol: String,
+}
+
+/// One hit of the `/search/` endpoint.
+#[allow(unused)]
+#[derive(Debug, Clone, Deserialize)]
+pub struct SearchResult {
+    pub uid: i64,
+    pub station: SearchStation,
+}
+
+#[allow(unused)]
+#[derive(Debug, Clone, Deserialize)]
+pub struct SearchStation {
+    pub name: String,
+}
+
+/// One station of the `/v2/map/bounds` endpoint.
+#[allow(unused)]
+#[derive(Debug, Clone, Deserialize)]
+pub struct MapStation {
+    pub uid: i64,
+    pub lat: f64,
+    pub lon: f64,
+    /// The station's AQI as text, "-" when it has no current reading.
+    pub aqi: String,
+    pub station: SearchStation,
+}
+
+impl MapStation {
+    #[allow(unused)]
+    pub fn aqi(&self) -> Option<u32> {
+        self.aqi.trim().parse().ok()
+    }
+}
+
+/// Just the envelope of a WAQI response. On errors `data` is a message rather than a feed.
+#[derive(Deserialize)]
+struct ApiStatus {
+    status: String,
+    #[serde(default)]
+    data: serde_json::Value,
+}
+
+/// A successfully parsed response: either the payload or the error WAQI reported.
+#[derive(Debug)]
+pub enum Envelope<T = PollutionData> {
+    Ok(Box<T>),
+    Error(String),
+}
+
+/// Parses a response body, looking at `data` only if WAQI says the request succeeded.
+pub fn parse_envelope<T: DeserializeOwned>(body: &str) -> Result<Envelope<T>, serde_json::Error> {
+    let status: ApiStatus = serde_json::from_str(body)?;
+    if status.status != "ok" {
+        let message = status.data.as_str().unwrap_or(&status.status).to_owned();
+        return Ok(Envelope::Error(message));
+    }
+
+    let response: ApiResponse<T> = serde_json::from_str(body)?;
+    Ok(Envelope::Ok(Box::new(response.data)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A WAQI feed response: PM2.5 dominant at 87, with a PM2.5 forecast and a UV one.
+    const FEED: &str = include_str!("../fuzz/corpus/parse_response/feed.json");
+
+    /// The reading in a feed response.
+    fn feed(body: &str) -> PollutionData {
+        match parse_envelope(body).unwrap() {
+            Envelope::Ok(data) => *data,
+            Envelope::Error(message) => panic!("WAQI error: {message}"),
+        }
+    }
+
+    #[test]
+    fn fractional_forecast_values_are_rounded() {
+        let data = feed(&FEED.replace(r#""max":138,"min":68"#, r#""max":137.4,"min":67.5"#));
+        let day = &data.forecast.daily_for("pm25").unwrap()[1];
+        assert_eq!((day.avg, day.max, day.min), (96, 137, 68));
+    }
+
+    #[test]
+    fn forecast_is_found_whatever_the_casing() {
+        let body = FEED
+            .replace(r#""dominentpol":"pm25""#, r#""dominentpol":"PM25""#)
+            .replace(r#""daily":{"pm25""#, r#""daily":{"Pm25""#);
+        let data = feed(&body);
+        assert_eq!(data.forecast.daily_for(&data.dominentpol).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn uv_forecast_is_parsed() {
+        let data = feed(FEED);
+        let days = data.forecast.daily_for("uvi").unwrap();
+        assert_eq!(days.len(), 1);
+        assert_eq!((days[0].day.as_str(), days[0].max), ("2026-10-15", 5));
+    }
+
+    #[test]
+    fn coords_need_exactly_two_finite_numbers() {
+        let city = |geo: Vec<f64>| City {
+            geo,
+            name: String::new(),
+            url: String::new(),
+            location: String::new(),
+        };
+        assert_eq!(
+            city(vec![35.6892, 51.389]).coords(),
+            Some((35.6892, 51.389))
+        );
+        assert_eq!(city(vec![]).coords(), None);
+        assert_eq!(city(vec![35.6892]).coords(), None);
+        assert_eq!(city(vec![35.6892, 51.389, 1.0]).coords(), None);
+        assert_eq!(city(vec![f64::NAN, 51.389]).coords(), None);
+        assert_eq!(city(vec![35.6892, f64::INFINITY]).coords(), None);
+    }
+
+    #[test]
+    fn unexpected_forecast_keys_are_dropped() {
+        let data = feed(include_str!(
+            "../fuzz/corpus/parse_response/feed_odd_forecast.json"
+        ));
+        // `pm10` isn't an array, `source` not even an object and `o3` is empty.
+        let mut keys: Vec<_> = data.forecast.daily.keys().collect();
+        keys.sort();
+        assert_eq!(keys, ["pm25"]);
+        // Of `pm25`, only the day with numbers survives.
+        let days = data.forecast.daily_for("pm25").unwrap();
+        assert_eq!(days.len(), 1);
+        assert_eq!(days[0].day, "2026-10-14");
+    }
+
+    #[test]
+    fn pollutant_spellings_are_normalized() {
+        for name in ["PM2.5", "pm2_5", "pm 2.5", "pm25"] {
+            assert_eq!(normalize_pollutant(name), "pm25", "{name}");
+        }
+        assert_eq!(normalize_pollutant("Ozone"), "o3");
+        assert_eq!(normalize_pollutant("Carbon Monoxide"), "co");
+
+        let data = feed(FEED);
+        for name in ["PM2.5", "pm2_5"] {
+            assert_eq!(data.forecast.daily_for(name).unwrap().len(), 2, "{name}");
+        }
+        assert!(data.forecast.daily_for("Ozone").is_none());
+    }
+
+    #[test]
+    fn dotted_dominant_pollutant_resolves() {
+        let data = feed(include_str!(
+            "../fuzz/corpus/parse_response/feed_dotted_pollutant.json"
+        ));
+        assert_eq!(data.dominentpol, "pm25");
+        assert_eq!(data.iaqi[&data.dominentpol].v, 87.0);
+    }
+}
@@ -0,0 +1,37 @@
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+/// Number of pages `len` entries take at `per_page` a page, at least one.
+pub fn count(len: usize, per_page: usize) -> usize {
+    len.div_ceil(per_page).max(1)
+}
+
+/// Callback data of the button opening `page` of the list named by `prefix`.
+fn callback(prefix: &str, page: usize) -> String {
+    format!("{prefix}{page}")
+}
+
+/// The page a button made by `keyboard` opens, if `data` belongs to the list named by `prefix`.
+pub fn from_callback(data: &str, prefix: &str) -> Option<usize> {
+    data.strip_prefix(prefix)?.parse().ok()
+}
+
+/// Prev and Next buttons around `page` (0-based) of `pages`, `None` if everything fits on one.
+pub fn keyboard(prefix: &str, page: usize, pages: usize) -> Option<InlineKeyboardMarkup> {
+    if pages <= 1 {
+        return None;
+    }
+    let mut row = Vec::new();
+    if page > 0 {
+        row.push(InlineKeyboardButton::callback(
+            "◀ Prev",
+            callback(prefix, page - 1),
+        ));
+    }
+    if page + 1 < pages {
+        row.push(InlineKeyboardButton::callback(
+            "Next ▶",
+            callback(prefix, page + 1),
+        ));
+    }
+    Some(InlineKeyboardMarkup::new([row]))
+}
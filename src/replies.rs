@@ -0,0 +1,46 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+};
+use teloxide::types::{ChatId, MessageId};
+
+/// Command messages whose reply is remembered. Older ones are forgotten first.
+const MAX_TRACKED: usize = 1000;
+
+/// The bot's reply to each recent command, so editing the command can replace its reply.
+#[derive(Default)]
+pub struct Replies {
+    inner: Mutex<Tracked>,
+}
+
+#[derive(Default)]
+struct Tracked {
+    replies: HashMap<(ChatId, MessageId), MessageId>,
+    /// Commands in the order they were answered.
+    order: VecDeque<(ChatId, MessageId)>,
+}
+
+impl Replies {
+    /// Remembers `reply` as the answer to the `command` message.
+    pub fn record(&self, chat_id: ChatId, command: MessageId, reply: MessageId) {
+        let mut tracked = self.inner.lock().unwrap();
+        let key = (chat_id, command);
+        if tracked.replies.insert(key, reply).is_none() {
+            tracked.order.push_back(key);
+        }
+        while tracked.order.len() > MAX_TRACKED {
+            if let Some(oldest) = tracked.order.pop_front() {
+                tracked.replies.remove(&oldest);
+            }
+        }
+    }
+
+    /// The reply to the `command` message, forgetting it.
+    pub fn take(&self, chat_id: ChatId, command: MessageId) -> Option<MessageId> {
+        let mut tracked = self.inner.lock().unwrap();
+        let key = (chat_id, command);
+        let reply = tracked.replies.remove(&key)?;
+        tracked.order.retain(|tracked_key| *tracked_key != key);
+        Some(reply)
+    }
+}
@@ -0,0 +1,169 @@
+use crate::{
+    ascii::ChatBot,
+    band_change_alert, forecast_alert, forecast_samples, format_digest,
+    model::PollutionData,
+    sample_of,
+    store::{AlertMode, Store},
+    waqi::Waqi,
+    Bot,
+};
+use chrono::{DateTime, Local, Timelike, Utc};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::{sync::Semaphore, task::JoinSet};
+
+/// Starts the background task delivering subscription digests and checking band-change
+/// alerts at the top of every hour, fetching at most `concurrency` cities at once. Digest
+/// hours are matched in each user's own time zone.
+pub fn spawn(bot: Bot, store: Store, waqi: Waqi, concurrency: usize) {
+    let permits = Arc::new(Semaphore::new(concurrency));
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(until_next_hour()).await;
+            deliver_digests(&bot, &store, &waqi, &permits, Utc::now()).await;
+        }
+    });
+}
+
+fn until_next_hour() -> Duration {
+    let now = Local::now();
+    let into_hour = Duration::from_secs((now.minute() * 60 + now.second()) as u64);
+    // A second of slack so we never wake up just before the hour and read the previous one.
+    Duration::from_secs(60 * 60) - into_hour + Duration::from_secs(1)
+}
+
+async fn deliver_digests(
+    bot: &Bot,
+    store: &Store,
+    waqi: &Waqi,
+    permits: &Arc<Semaphore>,
+    now: DateTime<Utc>,
+) {
+    let mut deliveries = Vec::new();
+    for (chat_id, subscriptions) in store.all_subscriptions().await {
+        let settings = store.settings(chat_id).await;
+        if settings.is_muted(now) {
+            continue;
+        }
+
+        let due: Vec<_> = subscriptions
+            .into_iter()
+            .filter(|s| settings.is_due(s, now))
+            .collect();
+        if due.is_empty() {
+            continue;
+        }
+        deliveries.push((chat_id, due, settings));
+    }
+
+    // Every target is fetched once per cycle no matter how many chats are subscribed to it.
+    let mut targets: HashMap<String, String> = HashMap::new();
+    for (_, due, _) in &deliveries {
+        for subscription in due {
+            targets
+                .entry(subscription.target.key())
+                .or_insert_with(|| subscription.target.feed_target());
+        }
+    }
+    let readings = fetch_all(waqi, permits, targets).await;
+    for (city, reading) in &readings {
+        let Ok(data) = reading else {
+            continue;
+        };
+        if let Some(sample) = sample_of(data) {
+            store.record_sample(city, sample).await;
+        }
+        store.record_forecast(city, forecast_samples(data)).await;
+    }
+
+    for (chat_id, due, settings) in deliveries {
+        let bot = ChatBot::new(bot.clone(), &settings);
+        let (hourly, digests): (Vec<_>, Vec<_>) = due.into_iter().partition(|s| s.mode.is_hourly());
+        let (watches, outlooks): (Vec<_>, Vec<_>) = hourly
+            .into_iter()
+            .partition(|s| s.mode == AlertMode::BandChange);
+
+        if !digests.is_empty() {
+            for text in format_digest(&digests, &readings, &settings) {
+                if let Err(e) = bot.send_message(chat_id, text).await {
+                    tracing::warn!("Failed to deliver digest to {chat_id}: {e}");
+                }
+            }
+        }
+
+        for watch in watches {
+            let key = watch.target.key();
+            let Some(Ok(data)) = readings.get(&key) else {
+                continue;
+            };
+            let (band, alert) = match band_change_alert(&watch, data, &settings) {
+                Ok(result) => result,
+                Err(e) => {
+                    tracing::warn!("{e}");
+                    continue;
+                }
+            };
+            store.set_last_band(chat_id, &key, band).await;
+            if let Some(alert) = alert {
+                if let Err(e) = bot.send_message(chat_id, alert).await {
+                    tracing::warn!("Failed to deliver band alert to {chat_id}: {e}");
+                }
+            }
+        }
+
+        for outlook in outlooks {
+            let key = outlook.target.key();
+            let Some(Ok(data)) = readings.get(&key) else {
+                continue;
+            };
+            let (announced, alert) = match forecast_alert(&outlook, data, &settings) {
+                Ok(result) => result,
+                Err(e) => {
+                    tracing::warn!("{e}");
+                    continue;
+                }
+            };
+            store.set_announced_days(chat_id, &key, announced).await;
+            if let Some(alert) = alert {
+                if let Err(e) = bot.send_message(chat_id, alert).await {
+                    tracing::warn!("Failed to deliver forecast alert to {chat_id}: {e}");
+                }
+            }
+        }
+    }
+}
+
+/// Fetches every target (keyed by `Target::key`), `permits` bounding how many run at once.
+pub async fn fetch_all(
+    waqi: &Waqi,
+    permits: &Arc<Semaphore>,
+    targets: HashMap<String, String>,
+) -> HashMap<String, Result<PollutionData, String>> {
+    let delay = waqi.inter_request_delay();
+    let mut tasks = JoinSet::new();
+    for (i, (key, target)) in targets.into_iter().enumerate() {
+        // With a politeness delay the fetches still overlap, but start one at a time.
+        if i > 0 && !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+        let waqi = waqi.clone();
+        let permits = permits.clone();
+        tasks.spawn(async move {
+            let reading = match permits.acquire_owned().await {
+                Ok(_permit) => waqi.feed(&target).await.map_err(|e| e.to_string()),
+                Err(e) => Err(e.to_string()),
+            };
+            (key, reading)
+        });
+    }
+
+    let mut readings = HashMap::new();
+    while let Some(result) = tasks.join_next().await {
+        match result {
+            Ok((key, reading)) => {
+                readings.insert(key, reading);
+            }
+            Err(e) => tracing::error!("Digest fetch task failed: {e}"),
+        }
+    }
+    readings
+}
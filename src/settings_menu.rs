@@ -0,0 +1,222 @@
+use crate::{
+    store::{
+        DigestLayout, Store, TimeFormat, UserSettings, ValueMode, Verbosity, MAX_FORECAST_DAYS,
+    },
+    Bot,
+};
+use std::time::Duration;
+use teloxide::{
+    prelude::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup},
+};
+
+/// Prefix of the callback data of every `/settings` button.
+const CALLBACK_PREFIX: &str = "settings:";
+
+/// Settings the `/settings` keyboard can change, each cycling to its next value on a tap.
+#[derive(Clone, Copy)]
+enum Setting {
+    Verbosity,
+    ValueMode,
+    ForecastDays,
+    DigestLayout,
+    TimeFormat,
+    WisForecast,
+    Conditions,
+    Cigarettes,
+    BandMarkers,
+    Ladder,
+    Ascii,
+}
+
+impl Setting {
+    const ALL: [Setting; 11] = [
+        Setting::Verbosity,
+        Setting::ValueMode,
+        Setting::ForecastDays,
+        Setting::DigestLayout,
+        Setting::TimeFormat,
+        Setting::WisForecast,
+        Setting::Conditions,
+        Setting::Cigarettes,
+        Setting::BandMarkers,
+        Setting::Ladder,
+        Setting::Ascii,
+    ];
+
+    fn key(self) -> &'static str {
+        match self {
+            Setting::Verbosity => "verbosity",
+            Setting::ValueMode => "valuemode",
+            Setting::ForecastDays => "forecastdays",
+            Setting::DigestLayout => "digestlayout",
+            Setting::TimeFormat => "timeformat",
+            Setting::WisForecast => "wisforecast",
+            Setting::Conditions => "conditions",
+            Setting::Cigarettes => "cigarettes",
+            Setting::BandMarkers => "bandmarkers",
+            Setting::Ladder => "ladder",
+            Setting::Ascii => "ascii",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Setting::Verbosity => "Verbosity",
+            Setting::ValueMode => "Headline value",
+            Setting::ForecastDays => "Forecast days",
+            Setting::DigestLayout => "Digest layout",
+            Setting::TimeFormat => "Clock",
+            Setting::WisForecast => "Forecast in /wis",
+            Setting::Conditions => "Weather conditions",
+            Setting::Cigarettes => "Cigarettes",
+            Setting::BandMarkers => "Band markers",
+            Setting::Ladder => "Band ladder",
+            Setting::Ascii => "ASCII only",
+        }
+    }
+
+    fn value(self, settings: &UserSettings) -> String {
+        match self {
+            Setting::Verbosity => settings.verbosity.to_string(),
+            Setting::ValueMode => settings.value_mode.to_string(),
+            Setting::ForecastDays => settings
+                .forecast_days
+                .map_or("all".to_owned(), |days| days.to_string()),
+            Setting::DigestLayout => settings.digest_layout.to_string(),
+            Setting::TimeFormat => settings.time_format.to_string(),
+            Setting::WisForecast => on_off(settings.include_forecast_in_wis),
+            Setting::Conditions => on_off(settings.show_conditions),
+            Setting::Cigarettes => on_off(settings.show_cigarettes),
+            Setting::BandMarkers => on_off(settings.band_markers),
+            Setting::Ladder => on_off(settings.show_ladder),
+            Setting::Ascii => on_off(settings.ascii),
+        }
+    }
+
+    fn cycle(self, settings: &mut UserSettings) {
+        match self {
+            Setting::Verbosity => {
+                settings.verbosity = match settings.verbosity {
+                    Verbosity::Brief => Verbosity::Normal,
+                    Verbosity::Normal => Verbosity::Detailed,
+                    Verbosity::Detailed => Verbosity::Brief,
+                }
+            }
+            Setting::ValueMode => {
+                settings.value_mode = match settings.value_mode {
+                    ValueMode::Aqi => ValueMode::Concentration,
+                    ValueMode::Concentration => ValueMode::Aqi,
+                }
+            }
+            Setting::ForecastDays => {
+                settings.forecast_days = match settings.forecast_days {
+                    None => Some(1),
+                    Some(days) if days < MAX_FORECAST_DAYS => Some(days + 1),
+                    Some(_) => None,
+                }
+            }
+            Setting::DigestLayout => {
+                settings.digest_layout = match settings.digest_layout {
+                    DigestLayout::Combined => DigestLayout::Separate,
+                    DigestLayout::Separate => DigestLayout::Combined,
+                }
+            }
+            Setting::TimeFormat => {
+                settings.time_format = match settings.time_format {
+                    TimeFormat::H12 => TimeFormat::H24,
+                    TimeFormat::H24 => TimeFormat::H12,
+                }
+            }
+            Setting::WisForecast => {
+                settings.include_forecast_in_wis = !settings.include_forecast_in_wis
+            }
+            Setting::Conditions => settings.show_conditions = !settings.show_conditions,
+            Setting::Cigarettes => settings.show_cigarettes = !settings.show_cigarettes,
+            Setting::BandMarkers => settings.band_markers = !settings.band_markers,
+            Setting::Ladder => settings.show_ladder = !settings.show_ladder,
+            Setting::Ascii => settings.ascii = !settings.ascii,
+        }
+    }
+
+    fn from_callback(data: &str) -> Option<Setting> {
+        let key = data.strip_prefix(CALLBACK_PREFIX)?;
+        Setting::ALL
+            .into_iter()
+            .find(|setting| setting.key() == key)
+    }
+}
+
+fn on_off(enabled: bool) -> String {
+    if enabled { "on" } else { "off" }.to_owned()
+}
+
+/// `/settings`: every setting of the chat, the ones without a button pointing at the command
+/// that changes them.
+pub fn render(settings: &UserSettings) -> String {
+    let mut text = "Your settings:\n".to_owned();
+    for setting in Setting::ALL {
+        text.push_str(&format!(
+            "{}: {}\n",
+            setting.label(),
+            setting.value(settings)
+        ));
+    }
+
+    let focus = settings.focus_pollutant.as_deref().unwrap_or("dominant");
+    let stale_after = match settings.stale_after_secs {
+        0 => "off".to_owned(),
+        secs => humantime::format_duration(Duration::from_secs(secs)).to_string(),
+    };
+    let cutoffs = settings
+        .band_cutoffs
+        .map_or("standard".to_owned(), |cutoffs| {
+            cutoffs.map(|cutoff| cutoff.to_string()).join(" ")
+        });
+    let timezone = settings.timezone.map_or("server time", |tz| tz.name());
+    let standard = settings
+        .standard
+        .map_or("not chosen yet".to_owned(), |standard| standard.to_string());
+    text.push_str(&format!(
+        "Focus pollutant: {focus} (/focus)\n\
+         Warn about readings older than: {stale_after} (/staleafter)\n\
+         Band cutoffs: {cutoffs} (/bands)\n\
+         Time zone: {timezone} (/timezone)\n\
+         Standard: {standard} (/standard)\n\n\
+         Tap a button to change a setting."
+    ));
+    text
+}
+
+/// One button per setting, showing its current value.
+pub fn keyboard(settings: &UserSettings) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(Setting::ALL.into_iter().map(|setting| {
+        [InlineKeyboardButton::callback(
+            format!("{}: {}", setting.label(), setting.value(settings)),
+            format!("{CALLBACK_PREFIX}{}", setting.key()),
+        )]
+    }))
+}
+
+/// Applies a tap on a `/settings` button and updates the message in place.
+pub async fn answer_callback(bot: Bot, query: CallbackQuery, store: Store) -> ResponseResult<()> {
+    let setting = query.data.as_deref().and_then(Setting::from_callback);
+    let (Some(setting), Some(message)) = (setting, &query.message) else {
+        bot.answer_callback_query(query.id).await?;
+        return Ok(());
+    };
+
+    let chat_id = message.chat().id;
+    store
+        .update_settings(chat_id, |settings| setting.cycle(settings))
+        .await;
+    let settings = store.settings(chat_id).await;
+
+    bot.edit_message_text(chat_id, message.id(), render(&settings))
+        .reply_markup(keyboard(&settings))
+        .await?;
+    bot.answer_callback_query(query.id)
+        .text(format!("{}: {}", setting.label(), setting.value(&settings)))
+        .await?;
+    Ok(())
+}
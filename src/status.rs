@@ -0,0 +1,116 @@
+use crate::{waqi::Waqi, Bot};
+use http_body_util::Full;
+use hyper::{
+    body::{Bytes, Incoming},
+    server::conn::http1,
+    service::service_fn,
+    Request, Response, StatusCode,
+};
+use hyper_util::rt::TokioIo;
+use std::{
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use teloxide::prelude::*;
+use tokio::net::TcpListener;
+
+/// How long the readiness probe waits before trying again.
+const PROBE_RETRY: Duration = Duration::from_secs(10);
+
+struct Readiness {
+    telegram: AtomicBool,
+    waqi: AtomicBool,
+    require_waqi: bool,
+}
+
+impl Readiness {
+    fn is_ready(&self) -> bool {
+        self.telegram.load(Ordering::Relaxed)
+            && (!self.require_waqi || self.waqi.load(Ordering::Relaxed))
+    }
+}
+
+/// Starts the HTTP status server on `port`, answering `/healthz` as soon as it's up and
+/// `/readyz` once Telegram (and, with `require_waqi`, a WAQI fetch of `probe_city`) has
+/// answered. Does nothing if the port is 0.
+pub fn spawn(port: u16, bot: Bot, waqi: Waqi, probe_city: String, require_waqi: bool) {
+    if port == 0 {
+        return;
+    }
+
+    let readiness = Arc::new(Readiness {
+        telegram: AtomicBool::new(false),
+        waqi: AtomicBool::new(false),
+        require_waqi,
+    });
+    tokio::spawn(probe(bot, waqi, probe_city, readiness.clone()));
+    tokio::spawn(async move {
+        let addr = SocketAddr::from(([0, 0, 0, 0], port));
+        match TcpListener::bind(addr).await {
+            Ok(listener) => {
+                tracing::info!("Status server listening on {addr}");
+                serve(listener, readiness).await;
+            }
+            Err(e) => tracing::error!("Failed to start the status server on {addr}: {e}"),
+        }
+    });
+}
+
+/// Retries until Telegram, then WAQI if required, have answered once.
+async fn probe(bot: Bot, waqi: Waqi, probe_city: String, readiness: Arc<Readiness>) {
+    while let Err(e) = bot.get_me().await {
+        tracing::warn!("Readiness probe couldn't reach Telegram: {e}");
+        tokio::time::sleep(PROBE_RETRY).await;
+    }
+    readiness.telegram.store(true, Ordering::Relaxed);
+
+    if readiness.require_waqi {
+        while let Err(e) = waqi.feed(&probe_city).await {
+            tracing::warn!("Readiness probe couldn't fetch {probe_city}: {e}");
+            tokio::time::sleep(PROBE_RETRY).await;
+        }
+        readiness.waqi.store(true, Ordering::Relaxed);
+    }
+}
+
+async fn serve(listener: TcpListener, readiness: Arc<Readiness>) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                tracing::warn!("Status server failed to accept a connection: {e}");
+                continue;
+            }
+        };
+        let readiness = readiness.clone();
+        tokio::spawn(async move {
+            let service = service_fn(move |request| {
+                let response = respond(&request, &readiness);
+                async move { Ok::<_, Infallible>(response) }
+            });
+            if let Err(e) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                tracing::debug!("Status connection failed: {e}");
+            }
+        });
+    }
+}
+
+fn respond(request: &Request<Incoming>, readiness: &Readiness) -> Response<Full<Bytes>> {
+    let (status, body) = match request.uri().path() {
+        "/healthz" => (StatusCode::OK, "ok"),
+        "/readyz" if readiness.is_ready() => (StatusCode::OK, "ready"),
+        "/readyz" => (StatusCode::SERVICE_UNAVAILABLE, "not ready"),
+        _ => (StatusCode::NOT_FOUND, "not found"),
+    };
+    let mut response = Response::new(Full::new(Bytes::from_static(body.as_bytes())));
+    *response.status_mut() = status;
+    response
+}
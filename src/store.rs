@@ -0,0 +1,831 @@
+use chrono::{DateTime, Local, NaiveDate, Timelike, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, ops::RangeInclusive, path::PathBuf, str::FromStr, sync::Arc};
+use teloxide::types::ChatId;
+use tokio::sync::Mutex;
+
+// ------------------- //
+// BEGIN User Settings //
+// ------------------- //
+
+/// How much of a reading `/wis` shows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Verbosity {
+    /// Only the verdict line and the dominant pollutant's emoji.
+    Brief,
+    /// Current reading plus the forecast of the dominant pollutant.
+    #[default]
+    Normal,
+    /// Everything in `Normal` plus every pollutant and the weather conditions.
+    Detailed,
+}
+
+impl FromStr for Verbosity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "brief" => Ok(Verbosity::Brief),
+            "normal" => Ok(Verbosity::Normal),
+            "detailed" => Ok(Verbosity::Detailed),
+            other => Err(format!("Unknown verbosity: {other}")),
+        }
+    }
+}
+
+impl std::fmt::Display for Verbosity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Verbosity::Brief => f.write_str("brief"),
+            Verbosity::Normal => f.write_str("normal"),
+            Verbosity::Detailed => f.write_str("detailed"),
+        }
+    }
+}
+
+/// What the headline number of a report is.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ValueMode {
+    /// The US AQI of the headline pollutant.
+    #[default]
+    Aqi,
+    /// Its concentration in µg/m³, where it can be worked out.
+    Concentration,
+}
+
+impl FromStr for ValueMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "aqi" => Ok(ValueMode::Aqi),
+            "concentration" => Ok(ValueMode::Concentration),
+            other => Err(format!("Unknown value mode: {other}")),
+        }
+    }
+}
+
+impl std::fmt::Display for ValueMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValueMode::Aqi => f.write_str("aqi"),
+            ValueMode::Concentration => f.write_str("concentration"),
+        }
+    }
+}
+
+/// How a digest of several subscriptions is delivered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DigestLayout {
+    /// One message, opening with the cities side by side when there are several.
+    #[default]
+    Combined,
+    /// One message per city.
+    Separate,
+}
+
+impl FromStr for DigestLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "combined" => Ok(DigestLayout::Combined),
+            "separate" => Ok(DigestLayout::Separate),
+            other => Err(format!("Unknown digest layout: {other}")),
+        }
+    }
+}
+
+impl std::fmt::Display for DigestLayout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DigestLayout::Combined => f.write_str("combined"),
+            DigestLayout::Separate => f.write_str("separate"),
+        }
+    }
+}
+
+/// Scale a reading is graded on besides WAQI's own US AQI.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Standard {
+    /// US EPA AQI, which every WAQI reading already is.
+    #[default]
+    Us,
+    /// The European CAQI, shown under the report.
+    Eu,
+}
+
+impl Standard {
+    /// The standard of wherever `(lat, lon)` is: the European CAQI within Europe, the US AQI
+    /// everywhere else.
+    pub fn at(lat: f64, lon: f64) -> Standard {
+        if (34.0..=72.0).contains(&lat) && (-25.0..=45.0).contains(&lon) {
+            Standard::Eu
+        } else {
+            Standard::Us
+        }
+    }
+}
+
+impl FromStr for Standard {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "us" | "epa" => Ok(Standard::Us),
+            "eu" | "caqi" => Ok(Standard::Eu),
+            other => Err(format!("Unknown standard: {other}")),
+        }
+    }
+}
+
+impl std::fmt::Display for Standard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Standard::Us => f.write_str("US EPA"),
+            Standard::Eu => f.write_str("EU CAQI"),
+        }
+    }
+}
+
+/// Whether times are shown on a 12 or a 24-hour clock.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeFormat {
+    /// "8:00 AM"
+    H12,
+    /// "08:00"
+    #[default]
+    H24,
+}
+
+impl TimeFormat {
+    /// `strftime` pattern of a time of day on this clock.
+    pub fn pattern(self) -> &'static str {
+        match self {
+            TimeFormat::H12 => "%-I:%M %p",
+            TimeFormat::H24 => "%H:%M",
+        }
+    }
+
+    /// The full `hour` (0-23) on this clock, e.g. "8:00 AM" or "08:00".
+    pub fn hour(self, hour: u8) -> String {
+        match self {
+            TimeFormat::H12 => {
+                let suffix = if hour < 12 { "AM" } else { "PM" };
+                let hour = match hour % 12 {
+                    0 => 12,
+                    hour => hour,
+                };
+                format!("{hour}:00 {suffix}")
+            }
+            TimeFormat::H24 => format!("{hour:02}:00"),
+        }
+    }
+}
+
+impl FromStr for TimeFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "12h" | "h12" | "12" => Ok(TimeFormat::H12),
+            "24h" | "h24" | "24" => Ok(TimeFormat::H24),
+            other => Err(format!("Unknown time format: {other}")),
+        }
+    }
+}
+
+impl std::fmt::Display for TimeFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TimeFormat::H12 => f.write_str("12h"),
+            TimeFormat::H24 => f.write_str("24h"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UserSettings {
+    pub verbosity: Verbosity,
+    /// Pollutant to headline instead of the station's dominant one.
+    pub focus_pollutant: Option<String>,
+    /// Show the "≈ N cigarettes" equivalent of PM2.5 (opt-in, some find it alarmist).
+    pub show_cigarettes: bool,
+    /// Readings older than this get a warning, 0 disables it.
+    pub stale_after_secs: u64,
+    /// Scheduled messages are held back until then.
+    pub muted_until: Option<DateTime<Utc>>,
+    /// Forecast days to show, `None` for every day the station has.
+    pub forecast_days: Option<u8>,
+    pub value_mode: ValueMode,
+    /// Show the weather conditions in detailed reports.
+    pub show_conditions: bool,
+    /// Highest AQI of each band from Good to Very Unhealthy, replacing the standard
+    /// 50/100/150/200/300. Anything above the last one is Hazardous.
+    pub band_cutoffs: Option<[u32; 5]>,
+    /// Append the forecast to `/wis` reports (`/forecast` always shows it).
+    pub include_forecast_in_wis: bool,
+    /// Add a 🔸 to the headline emoji when the AQI is in the upper half of its band.
+    pub band_markers: bool,
+    /// Report pinned by `/wis --pin`, unpinned when the next one is pinned.
+    pub pinned_report: Option<i32>,
+    /// Time zone subscription hours and shown times are in, `None` for the server's.
+    pub timezone: Option<Tz>,
+    /// Start normal and detailed reports with the ladder of band emoji.
+    pub show_ladder: bool,
+    pub digest_layout: DigestLayout,
+    pub time_format: TimeFormat,
+    /// Write reports without emoji, for clients that can't show them.
+    pub ascii: bool,
+    /// Scale reports are graded on, inferred from the station of the first `/wis` unless set
+    /// with `/standard`. `None` until then.
+    pub standard: Option<Standard>,
+    /// Whether `standard` was set with `/standard` rather than inferred.
+    pub standard_explicit: bool,
+}
+
+/// Most forecast days a user can ask for.
+pub const MAX_FORECAST_DAYS: u8 = 7;
+
+impl UserSettings {
+    /// How many forecast days to render.
+    pub fn forecast_limit(&self) -> usize {
+        self.forecast_days.map_or(usize::MAX, usize::from)
+    }
+
+    pub fn is_muted(&self, now: DateTime<Utc>) -> bool {
+        self.muted_until.is_some_and(|until| now < until)
+    }
+
+    /// Infers `standard` from a station at `coords` unless it's known already, returning
+    /// whether it was.
+    pub fn infer_standard(&mut self, coords: Option<(f64, f64)>) -> bool {
+        match (self.standard, coords) {
+            (None, Some((lat, lon))) => {
+                self.standard = Some(Standard::at(lat, lon));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// The hour of `now` in the user's time zone.
+    pub fn local_hour(&self, now: DateTime<Utc>) -> u8 {
+        let hour = match self.timezone {
+            Some(tz) => now.with_timezone(&tz).hour(),
+            None => now.with_timezone(&Local).hour(),
+        };
+        hour as u8
+    }
+
+    /// Whether `subscription` should be delivered in the hour starting at `now`.
+    pub fn is_due(&self, subscription: &Subscription, now: DateTime<Utc>) -> bool {
+        subscription.mode.is_hourly() || subscription.hour == self.local_hour(now)
+    }
+}
+
+impl Default for UserSettings {
+    fn default() -> Self {
+        Self {
+            verbosity: Verbosity::default(),
+            focus_pollutant: None,
+            show_cigarettes: false,
+            stale_after_secs: 3 * 60 * 60,
+            muted_until: None,
+            forecast_days: None,
+            value_mode: ValueMode::default(),
+            show_conditions: true,
+            band_cutoffs: None,
+            include_forecast_in_wis: true,
+            band_markers: false,
+            pinned_report: None,
+            timezone: None,
+            show_ladder: true,
+            digest_layout: DigestLayout::default(),
+            time_format: TimeFormat::default(),
+            ascii: false,
+            standard: None,
+            standard_explicit: false,
+        }
+    }
+}
+
+// ------------------- //
+// BEGIN Subscriptions //
+// ------------------- //
+
+/// What a subscription reports on.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Target {
+    /// A station looked up by name (or `@uid`).
+    Named(String),
+    /// Whichever station is nearest to `(lat, lon)`, resolved again on every delivery.
+    Geo(f64, f64),
+}
+
+impl Target {
+    /// The target as passed to `Waqi::feed`.
+    pub fn feed_target(&self) -> String {
+        match self {
+            Target::Named(city) => city.clone(),
+            Target::Geo(lat, lon) => format!("geo:{lat};{lon}"),
+        }
+    }
+
+    /// Identifies the target regardless of how the name was capitalized.
+    pub fn key(&self) -> String {
+        self.feed_target().to_lowercase()
+    }
+}
+
+impl std::fmt::Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Target::Named(city) => f.write_str(city),
+            Target::Geo(lat, lon) => write!(f, "the station nearest to {lat},{lon}"),
+        }
+    }
+}
+
+/// When a subscription sends something.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AlertMode {
+    /// A digest every day at the subscription's hour.
+    #[default]
+    #[serde(rename = "daily")]
+    Daily,
+    /// An alert whenever the reading moves into another band, checked every hour.
+    #[serde(rename = "on_band_change")]
+    BandChange,
+    /// An alert whenever an upcoming day is forecast to be in a worse band than the current
+    /// reading, checked every hour and sent once per day.
+    #[serde(rename = "on_forecast_worse")]
+    ForecastWorse,
+}
+
+impl AlertMode {
+    /// Whether the subscription is checked every hour rather than at its own.
+    pub fn is_hourly(self) -> bool {
+        self != AlertMode::Daily
+    }
+}
+
+/// A daily digest of `target` delivered at `hour` (in the chat's time zone), or band-change
+/// alerts.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Subscription {
+    /// Saved as `city` before coordinates could be subscribed to.
+    #[serde(alias = "city")]
+    pub target: Target,
+    /// Unused by band-change alerts.
+    pub hour: u8,
+    #[serde(default)]
+    pub mode: AlertMode,
+    /// Band of the last reading a band-change alert saw, 0 for Good.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_band: Option<u8>,
+    /// WAQI id of the station a city name resolved to when subscribing, so two spellings of
+    /// the same station can be told apart from two stations. Unset for coordinates, whose
+    /// station is looked up again on every delivery.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub station: Option<u32>,
+    /// `(lat, lon)` of the station a city name resolved to when subscribing, so regional
+    /// alerts can tell where it is. Coordinate targets carry their own.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coords: Option<(f64, f64)>,
+    /// Forecast days (`YYYY-MM-DD`) a forecast alert has already warned about.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub announced_days: Vec<String>,
+}
+
+/// Everything a chat has set up, as exported by `/exportsettings`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserExport {
+    #[serde(default)]
+    pub settings: UserSettings,
+    #[serde(default)]
+    pub subscriptions: Vec<Subscription>,
+}
+
+impl Subscription {
+    /// Whether `other` is for the same target, or for a name that resolved to the same station.
+    pub fn duplicates(&self, other: &Subscription) -> bool {
+        self.target.key() == other.target.key()
+            || self.station.is_some() && self.station == other.station
+    }
+
+    /// Where the subscription reports on, if that's known.
+    pub fn location(&self) -> Option<(f64, f64)> {
+        match self.target {
+            Target::Geo(lat, lon) => Some((lat, lon)),
+            Target::Named(_) => self.coords,
+        }
+    }
+
+    /// When the subscription sends something, e.g. "every day at 08:00".
+    pub fn schedule(&self, time_format: TimeFormat) -> String {
+        match self.mode {
+            AlertMode::Daily => format!("every day at {}", time_format.hour(self.hour)),
+            AlertMode::BandChange => "whenever the band changes".to_owned(),
+            AlertMode::ForecastWorse => "whenever a worse day is forecast".to_owned(),
+        }
+    }
+
+    /// The `/subscribe` argument that sets up the same schedule.
+    pub fn schedule_arg(&self) -> String {
+        match self.mode {
+            AlertMode::Daily => self.hour.to_string(),
+            AlertMode::BandChange => "onchange".to_owned(),
+            AlertMode::ForecastWorse => "onforecast".to_owned(),
+        }
+    }
+}
+
+// ------------- //
+// BEGIN History //
+// ------------- //
+
+/// Days of samples kept per city.
+const HISTORY_DAYS: i64 = 30;
+
+/// The overall AQI a city had on a day (the station's local date).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sample {
+    pub date: NaiveDate,
+    pub aqi: u32,
+}
+
+// ----------- //
+// BEGIN Store //
+// ----------- //
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Data {
+    users: HashMap<ChatId, UserSettings>,
+    subscriptions: HashMap<ChatId, Vec<Subscription>>,
+    /// Daily samples per lowercased city name, oldest first.
+    history: HashMap<String, Vec<Sample>>,
+    /// Forecast AQI per lowercased city name and day, as last seen before that day, oldest
+    /// first.
+    forecasts: HashMap<String, Vec<Sample>>,
+}
+
+/// Per-user state, kept in memory and written to a JSON file after every change.
+#[derive(Clone)]
+pub struct Store {
+    path: PathBuf,
+    data: Arc<Mutex<Data>>,
+}
+
+impl Store {
+    /// Loads the store from `path`, starting empty if the file doesn't exist yet.
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self, String> {
+        let path = path.into();
+        let data = match std::fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .map_err(|e| format!("Failed to parse {}: {e}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Data::default(),
+            Err(e) => return Err(format!("Failed to read {}: {e}", path.display())),
+        };
+
+        Ok(Self {
+            path,
+            data: Arc::new(Mutex::new(data)),
+        })
+    }
+
+    pub async fn settings(&self, chat_id: ChatId) -> UserSettings {
+        let data = self.data.lock().await;
+        data.users.get(&chat_id).cloned().unwrap_or_default()
+    }
+
+    pub async fn update_settings(&self, chat_id: ChatId, f: impl FnOnce(&mut UserSettings)) {
+        let mut data = self.data.lock().await;
+        f(data.users.entry(chat_id).or_default());
+        self.save(&data).await;
+    }
+
+    pub async fn subscriptions(&self, chat_id: ChatId) -> Vec<Subscription> {
+        let data = self.data.lock().await;
+        data.subscriptions
+            .get(&chat_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Every chat with at least one subscription.
+    pub async fn all_subscriptions(&self) -> Vec<(ChatId, Vec<Subscription>)> {
+        let data = self.data.lock().await;
+        data.subscriptions
+            .iter()
+            .filter(|(_, subscriptions)| !subscriptions.is_empty())
+            .map(|(chat_id, subscriptions)| (*chat_id, subscriptions.clone()))
+            .collect()
+    }
+
+    /// Every chat with settings or subscriptions.
+    pub async fn known_chats(&self) -> Vec<ChatId> {
+        let data = self.data.lock().await;
+        let mut chats: Vec<ChatId> = data
+            .users
+            .keys()
+            .chain(data.subscriptions.keys())
+            .copied()
+            .collect();
+        chats.sort_by_key(|chat_id| chat_id.0);
+        chats.dedup();
+        chats
+    }
+
+    /// Adds `subscription`, replacing and returning the existing one if the target, or the
+    /// station it resolved to, is already subscribed.
+    pub async fn add_subscription(
+        &self,
+        chat_id: ChatId,
+        subscription: Subscription,
+    ) -> Option<Subscription> {
+        let mut data = self.data.lock().await;
+        let subscriptions = data.subscriptions.entry(chat_id).or_default();
+        let replaced = match subscriptions
+            .iter_mut()
+            .find(|s| s.duplicates(&subscription))
+        {
+            Some(existing) => Some(std::mem::replace(existing, subscription)),
+            None => {
+                subscriptions.push(subscription);
+                None
+            }
+        };
+        self.save(&data).await;
+        replaced
+    }
+
+    /// Every chat with a subscription located inside the `lat` and `lon` ranges.
+    pub async fn chats_within(
+        &self,
+        lat: RangeInclusive<f64>,
+        lon: RangeInclusive<f64>,
+    ) -> Vec<ChatId> {
+        let data = self.data.lock().await;
+        let mut chats: Vec<ChatId> = data
+            .subscriptions
+            .iter()
+            .filter(|(_, subscriptions)| {
+                subscriptions
+                    .iter()
+                    .filter_map(Subscription::location)
+                    .any(|(sub_lat, sub_lon)| lat.contains(&sub_lat) && lon.contains(&sub_lon))
+            })
+            .map(|(chat_id, _)| *chat_id)
+            .collect();
+        chats.sort_by_key(|chat_id| chat_id.0);
+        chats
+    }
+
+    /// Remembers the band a band-change alert for the target keyed `key` last saw.
+    pub async fn set_last_band(&self, chat_id: ChatId, key: &str, band: u8) {
+        let mut data = self.data.lock().await;
+        let Some(subscription) = data
+            .subscriptions
+            .get_mut(&chat_id)
+            .and_then(|subscriptions| subscriptions.iter_mut().find(|s| s.target.key() == key))
+        else {
+            return;
+        };
+        if subscription.last_band != Some(band) {
+            subscription.last_band = Some(band);
+            self.save(&data).await;
+        }
+    }
+
+    /// Replaces the forecast days the subscription of `chat_id` to `key` has warned about.
+    pub async fn set_announced_days(&self, chat_id: ChatId, key: &str, days: Vec<String>) {
+        let mut data = self.data.lock().await;
+        let Some(subscription) = data
+            .subscriptions
+            .get_mut(&chat_id)
+            .and_then(|subscriptions| subscriptions.iter_mut().find(|s| s.target.key() == key))
+        else {
+            return;
+        };
+        if subscription.announced_days != days {
+            subscription.announced_days = days;
+            self.save(&data).await;
+        }
+    }
+
+    /// Removes the subscription to `target`, returning whether there was one.
+    pub async fn remove_subscription(&self, chat_id: ChatId, target: &Target) -> bool {
+        let mut data = self.data.lock().await;
+        let Some(subscriptions) = data.subscriptions.get_mut(&chat_id) else {
+            return false;
+        };
+        let before = subscriptions.len();
+        subscriptions.retain(|s| s.target.key() != target.key());
+        let removed = subscriptions.len() != before;
+        if removed {
+            self.save(&data).await;
+        }
+        removed
+    }
+
+    /// Removes the subscription at `index` of `subscriptions`, returning it if there was one.
+    pub async fn remove_subscription_at(
+        &self,
+        chat_id: ChatId,
+        index: usize,
+    ) -> Option<Subscription> {
+        let mut data = self.data.lock().await;
+        let subscriptions = data.subscriptions.get_mut(&chat_id)?;
+        if index >= subscriptions.len() {
+            return None;
+        }
+        let removed = subscriptions.remove(index);
+        self.save(&data).await;
+        Some(removed)
+    }
+
+    /// Records the city's AQI for `sample.date`, replacing an earlier sample of the same day and
+    /// dropping the ones older than the retention window.
+    pub async fn record_sample(&self, city: &str, sample: Sample) {
+        let mut data = self.data.lock().await;
+        let samples = data.history.entry(city.to_lowercase()).or_default();
+        match samples.iter_mut().find(|s| s.date == sample.date) {
+            Some(existing) => existing.aqi = sample.aqi,
+            None => {
+                samples.push(sample.clone());
+                samples.sort_by_key(|s| s.date);
+            }
+        }
+        let cutoff = sample.date - chrono::Duration::days(HISTORY_DAYS);
+        samples.retain(|s| s.date > cutoff);
+        self.save(&data).await;
+    }
+
+    /// Saves the forecast `days` of `city`, replacing what an earlier forecast said about them.
+    pub async fn record_forecast(&self, city: &str, days: Vec<Sample>) {
+        let Some(first) = days.iter().map(|day| day.date).min() else {
+            return;
+        };
+        let mut data = self.data.lock().await;
+        let forecasts = data.forecasts.entry(city.to_lowercase()).or_default();
+        for day in days {
+            match forecasts.iter_mut().find(|f| f.date == day.date) {
+                Some(existing) => existing.aqi = day.aqi,
+                None => forecasts.push(day),
+            }
+        }
+        forecasts.sort_by_key(|f| f.date);
+        let cutoff = first - chrono::Duration::days(HISTORY_DAYS);
+        forecasts.retain(|f| f.date > cutoff);
+        self.save(&data).await;
+    }
+
+    /// Recorded forecasts of `city`, oldest day first.
+    pub async fn forecasts(&self, city: &str) -> Vec<Sample> {
+        let data = self.data.lock().await;
+        data.forecasts
+            .get(&city.to_lowercase())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub async fn export(&self, chat_id: ChatId) -> UserExport {
+        let data = self.data.lock().await;
+        UserExport {
+            settings: data.users.get(&chat_id).cloned().unwrap_or_default(),
+            subscriptions: data
+                .subscriptions
+                .get(&chat_id)
+                .cloned()
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Replaces the settings and subscriptions of `chat_id` with `export`.
+    pub async fn import(&self, chat_id: ChatId, export: UserExport) {
+        let mut data = self.data.lock().await;
+        data.users.insert(chat_id, export.settings);
+        data.subscriptions.insert(chat_id, export.subscriptions);
+        self.save(&data).await;
+    }
+
+    /// Recorded samples of `city`, oldest first.
+    pub async fn history(&self, city: &str) -> Vec<Sample> {
+        let data = self.data.lock().await;
+        data.history
+            .get(&city.to_lowercase())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    async fn save(&self, data: &Data) {
+        let contents = match serde_json::to_string_pretty(data) {
+            Ok(contents) => contents,
+            Err(e) => {
+                tracing::error!("Failed to serialize store: {e}");
+                return;
+            }
+        };
+        if let Err(e) = tokio::fs::write(&self.path, contents).await {
+            tracing::error!("Failed to write {}: {e}", self.path.display());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hours_on_either_clock() {
+        let hours = |time_format: TimeFormat| [0, 12, 20].map(|hour| time_format.hour(hour));
+        assert_eq!(hours(TimeFormat::H12), ["12:00 AM", "12:00 PM", "8:00 PM"]);
+        assert_eq!(hours(TimeFormat::H24), ["00:00", "12:00", "20:00"]);
+    }
+
+    #[test]
+    fn times_on_either_clock() {
+        let times = |time_format: TimeFormat| {
+            ["00:05", "12:00", "20:30"].map(|time| {
+                chrono::NaiveTime::parse_from_str(time, "%H:%M")
+                    .unwrap()
+                    .format(time_format.pattern())
+                    .to_string()
+            })
+        };
+        assert_eq!(times(TimeFormat::H12), ["12:05 AM", "12:00 PM", "8:30 PM"]);
+        assert_eq!(times(TimeFormat::H24), ["00:05", "12:00", "20:30"]);
+    }
+
+    #[test]
+    fn the_standard_is_inferred_once() {
+        let berlin = Some((52.52, 13.405));
+        let tehran = Some((35.6892, 51.389));
+
+        let mut settings = UserSettings::default();
+        assert!(!settings.infer_standard(None));
+        assert_eq!(settings.standard, None);
+        assert!(settings.infer_standard(berlin));
+        assert_eq!(settings.standard, Some(Standard::Eu));
+        // Later lookups elsewhere keep it.
+        assert!(!settings.infer_standard(tehran));
+        assert_eq!(settings.standard, Some(Standard::Eu));
+
+        let mut settings = UserSettings::default();
+        assert!(settings.infer_standard(tehran));
+        assert_eq!(settings.standard, Some(Standard::Us));
+
+        // Neither is a standard set with `/standard` overridden.
+        let mut settings = UserSettings {
+            standard: Some(Standard::Us),
+            standard_explicit: true,
+            ..UserSettings::default()
+        };
+        assert!(!settings.infer_standard(berlin));
+        assert_eq!(settings.standard, Some(Standard::Us));
+    }
+
+    #[test]
+    fn hours_are_local_to_the_users_time_zone() {
+        // 06:00 UTC is 09:30 in Tehran, half an hour off any whole-hour zone.
+        let now = "2026-10-15T06:00:00Z".parse().unwrap();
+        let tehran = UserSettings {
+            timezone: Some(chrono_tz::Asia::Tehran),
+            ..UserSettings::default()
+        };
+        let utc = UserSettings {
+            timezone: Some(chrono_tz::UTC),
+            ..UserSettings::default()
+        };
+        assert_eq!(tehran.local_hour(now), 9);
+        assert_eq!(utc.local_hour(now), 6);
+
+        let daily_at = |hour| Subscription {
+            target: Target::Named("tehran".to_owned()),
+            hour,
+            mode: AlertMode::Daily,
+            last_band: None,
+            station: None,
+            coords: None,
+            announced_days: Vec::new(),
+        };
+        assert!(tehran.is_due(&daily_at(9), now));
+        assert!(!tehran.is_due(&daily_at(6), now));
+        assert!(utc.is_due(&daily_at(6), now));
+        let band_change = Subscription {
+            mode: AlertMode::BandChange,
+            ..daily_at(0)
+        };
+        assert!(tehran.is_due(&band_change, now));
+    }
+}
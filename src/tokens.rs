@@ -0,0 +1,98 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// How long a token that hit its quota is left alone.
+const OVER_QUOTA_COOLDOWN: Duration = Duration::from_secs(15 * 60);
+
+/// WAQI tokens handed out round-robin, skipping ones that recently went over quota.
+pub struct TokenPool {
+    tokens: Vec<TokenState>,
+    next: AtomicUsize,
+}
+
+struct TokenState {
+    token: String,
+    requests: AtomicU64,
+    over_quota: AtomicU64,
+    cooldown_until: Mutex<Option<Instant>>,
+}
+
+/// Snapshot of a token's usage, safe to show since the token itself is masked.
+pub struct TokenHealth {
+    pub label: String,
+    pub requests: u64,
+    pub over_quota: u64,
+    pub cooling_down_for: Option<Duration>,
+}
+
+impl TokenPool {
+    pub fn new(tokens: Vec<String>) -> Self {
+        Self {
+            tokens: tokens
+                .into_iter()
+                .map(|token| TokenState {
+                    token,
+                    requests: AtomicU64::new(0),
+                    over_quota: AtomicU64::new(0),
+                    cooldown_until: Mutex::new(None),
+                })
+                .collect(),
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    /// The next token that isn't cooling down, with its index for reporting back.
+    pub fn next(&self) -> Option<(usize, &str)> {
+        let now = Instant::now();
+        for _ in 0..self.tokens.len() {
+            let i = self.next.fetch_add(1, Ordering::Relaxed) % self.tokens.len();
+            let state = &self.tokens[i];
+            let mut cooldown = state.cooldown_until.lock().unwrap();
+            match *cooldown {
+                Some(until) if until > now => continue,
+                Some(_) => *cooldown = None,
+                None => {}
+            }
+            state.requests.fetch_add(1, Ordering::Relaxed);
+            return Some((i, &state.token));
+        }
+        None
+    }
+
+    pub fn mark_over_quota(&self, i: usize) {
+        let state = &self.tokens[i];
+        state.over_quota.fetch_add(1, Ordering::Relaxed);
+        *state.cooldown_until.lock().unwrap() = Some(Instant::now() + OVER_QUOTA_COOLDOWN);
+    }
+
+    pub fn health(&self) -> Vec<TokenHealth> {
+        let now = Instant::now();
+        self.tokens
+            .iter()
+            .map(|state| TokenHealth {
+                label: mask(&state.token),
+                requests: state.requests.load(Ordering::Relaxed),
+                over_quota: state.over_quota.load(Ordering::Relaxed),
+                cooling_down_for: state
+                    .cooldown_until
+                    .lock()
+                    .unwrap()
+                    .and_then(|until| until.checked_duration_since(now)),
+            })
+            .collect()
+    }
+}
+
+fn mask(token: &str) -> String {
+    let prefix: String = token.chars().take(4).collect();
+    format!("{prefix}…")
+}
@@ -0,0 +1,99 @@
+use serde::Deserialize;
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// The version this binary was built from.
+pub const CURRENT: &str = env!("CARGO_PKG_VERSION");
+
+const LATEST_RELEASE_URL: &str =
+    "https://api.github.com/repos/tholoo/will-i-suffocate/releases/latest";
+const RELEASES_PAGE: &str = "https://github.com/tholoo/will-i-suffocate/releases";
+
+/// How long a successful release check is reused.
+const CHECK_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Tag of the latest release and when it was fetched.
+static LATEST: Mutex<Option<(Instant, String)>> = Mutex::new(None);
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+}
+
+/// Renders `/version`, asking GitHub for the latest release if `check_updates` is set.
+pub async fn render(check_updates: bool) -> String {
+    let mut text = format!("wison {CURRENT}");
+    if !check_updates {
+        return text;
+    }
+
+    if let Some(latest) = latest_release().await {
+        if is_newer(&latest, CURRENT) {
+            text.push_str(&format!(
+                "\nA newer version is available: {latest}\n{RELEASES_PAGE}"
+            ));
+        } else {
+            text.push_str("\nThis is the latest release.");
+        }
+    }
+    text
+}
+
+/// The tag of the latest GitHub release, or `None` if GitHub couldn't be reached.
+async fn latest_release() -> Option<String> {
+    if let Some(tag) = cached_release() {
+        return Some(tag);
+    }
+
+    match fetch_latest_release().await {
+        Ok(tag) => {
+            *LATEST.lock().unwrap() = Some((Instant::now(), tag.clone()));
+            Some(tag)
+        }
+        Err(e) => {
+            tracing::debug!("Update check failed: {e}");
+            None
+        }
+    }
+}
+
+fn cached_release() -> Option<String> {
+    let latest = LATEST.lock().unwrap();
+    let (checked, tag) = latest.as_ref()?;
+    (checked.elapsed() < CHECK_INTERVAL).then(|| tag.clone())
+}
+
+async fn fetch_latest_release() -> Result<String, reqwest::Error> {
+    // GitHub rejects requests without a user agent.
+    let client = reqwest::Client::builder()
+        .user_agent(concat!("wison/", env!("CARGO_PKG_VERSION")))
+        .timeout(CHECK_TIMEOUT)
+        .build()?;
+    let release: Release = client
+        .get(LATEST_RELEASE_URL)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(release.tag_name)
+}
+
+/// Whether release tag `latest` (e.g. `v0.2.0`) is a later version than `current`. Tags that
+/// aren't dotted numbers never count as newer.
+fn is_newer(latest: &str, current: &str) -> bool {
+    match (parse_version(latest), parse_version(current)) {
+        (Some(latest), Some(current)) => latest > current,
+        _ => false,
+    }
+}
+
+/// Parses `v1.2.3` or `1.2.3-beta` into `[1, 2, 3]`, ignoring any pre-release suffix.
+fn parse_version(version: &str) -> Option<Vec<u64>> {
+    let version = version.trim().trim_start_matches('v');
+    let version = version.split(['-', '+']).next()?;
+    version.split('.').map(|part| part.parse().ok()).collect()
+}
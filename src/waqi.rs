@@ -0,0 +1,637 @@
+use crate::{
+    cache::TtlCache,
+    config::Config,
+    demo,
+    input::clean_city,
+    limiter::RateLimiter,
+    model::{parse_envelope, Envelope, HistoricalDay, MapStation, PollutionData, SearchResult},
+    tokens::{TokenHealth, TokenPool},
+};
+use chrono::{NaiveDate, Utc};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::time::timeout;
+
+/// How long a single WAQI request may take.
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Characters of a non-JSON response body that make it into the logs.
+const NON_JSON_SNIPPET_CHARS: usize = 200;
+
+/// Why a WAQI request failed.
+#[derive(Debug)]
+pub enum WaqiError {
+    /// No station goes by the requested name.
+    CityNotFound,
+    /// Any other error WAQI reported.
+    Api(String),
+    /// WAQI answered, but with a reading that can't be right.
+    Implausible(String),
+    Request(reqwest::Error),
+    Timeout,
+    Parse(serde_json::Error),
+    /// WAQI or a proxy in front of it answered with something other than JSON, like an HTML
+    /// error page.
+    NonJson {
+        status: u16,
+    },
+    RateLimited,
+    OverQuota,
+    /// The token's tier or the station has no history for the requested day.
+    HistoryUnavailable,
+    /// Demo mode only has the bundled cities.
+    Demo,
+}
+
+impl std::fmt::Display for WaqiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WaqiError::CityNotFound => f.write_str("API returned an error: Unknown station"),
+            WaqiError::Api(message) => write!(f, "API returned an error: {message}"),
+            WaqiError::Implausible(message) => write!(f, "Implausible reading: {message}"),
+            WaqiError::Request(e) => write!(f, "Request failed: {e}"),
+            WaqiError::Timeout => f.write_str("Request timed out"),
+            WaqiError::Parse(e) => write!(f, "Failed to parse the response: {e}"),
+            WaqiError::NonJson { status } => {
+                write!(f, "Upstream returned a non-JSON response (status {status})")
+            }
+            WaqiError::RateLimited => f.write_str("Global WAQI rate limit reached"),
+            WaqiError::OverQuota => f.write_str("All WAQI tokens are over quota"),
+            WaqiError::HistoryUnavailable => {
+                f.write_str("Historical data is not available for this token or station")
+            }
+            WaqiError::Demo => write!(
+                f,
+                "Demo mode: only these cities are available: {}",
+                demo::cities()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for WaqiError {}
+
+/// Client for the WAQI feed API with response caching, a global request budget and a pool of
+/// tokens to spread the load over.
+#[derive(Clone)]
+pub struct Waqi {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    client: reqwest::Client,
+    base_url: String,
+    /// Same API on another host, for when `base_url` can't be connected to.
+    fallback_base_url: Option<String>,
+    tokens: TokenPool,
+    cache: TtlCache<PollutionData>,
+    /// Names that recently matched no station, so they fail without another round trip.
+    not_found: TtlCache<NotFound>,
+    limiter: RateLimiter<()>,
+    slow_fetch_threshold_ms: AtomicU64,
+    slow_fetches: AtomicU64,
+    inter_request_delay_ms: AtomicU64,
+    /// Serve `demo` fixtures instead of asking WAQI.
+    demo: bool,
+}
+
+/// A cached feed as saved to `cache_file`. The time is on the wall clock so it still means
+/// something after a restart.
+#[derive(Serialize, Deserialize)]
+struct PersistedFeed {
+    key: String,
+    /// Unix timestamp of when the feed was cached.
+    cached_at: i64,
+    data: PollutionData,
+}
+
+/// The HTTP side of the last response a request got.
+pub struct ResponseMeta {
+    pub status: u16,
+    /// Host that answered, the fallback's if the base URL couldn't be connected to.
+    pub host: String,
+    pub body_bytes: usize,
+}
+
+/// What fetching a feed target straight from WAQI looked like, for `/probe`.
+pub struct Probe {
+    /// Whether `feed` would have answered from the cache instead.
+    pub cached: bool,
+    /// `None` if no response came back at all.
+    pub response: Option<ResponseMeta>,
+    pub elapsed: Duration,
+    pub result: Result<PollutionData, WaqiError>,
+}
+
+/// What's known about a name that matched no station.
+#[derive(Clone)]
+enum NotFound {
+    /// Nobody has searched for a close match yet.
+    Unsearched,
+    Closest(SearchResult),
+    NoMatch,
+}
+
+impl Waqi {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                client: reqwest::Client::new(),
+                base_url: config.api_base().to_owned(),
+                fallback_base_url: config.api_fallback_base().map(str::to_owned),
+                tokens: TokenPool::new(config.aqi_tokens()),
+                cache: TtlCache::new(Duration::from_secs(config.cache_ttl_secs)),
+                not_found: TtlCache::new(Duration::from_secs(config.not_found_ttl_secs)),
+                limiter: RateLimiter::per_minute(config.rate_limit.global_per_minute),
+                slow_fetch_threshold_ms: AtomicU64::new(config.slow_fetch_ms),
+                slow_fetches: AtomicU64::new(0),
+                inter_request_delay_ms: AtomicU64::new(config.inter_request_delay_ms),
+                demo: config.is_demo(),
+            }),
+        }
+    }
+
+    /// Applies the settings of a reloaded `config` that can change at runtime. The client keeps
+    /// its tokens and base URL.
+    pub fn apply_config(&self, config: &Config) {
+        let inner = &self.inner;
+        inner
+            .cache
+            .set_ttl(Duration::from_secs(config.cache_ttl_secs));
+        inner
+            .not_found
+            .set_ttl(Duration::from_secs(config.not_found_ttl_secs));
+        inner.limiter.set_limit(config.rate_limit.global_per_minute);
+        inner
+            .slow_fetch_threshold_ms
+            .store(config.slow_fetch_ms, Ordering::Relaxed);
+        inner
+            .inter_request_delay_ms
+            .store(config.inter_request_delay_ms, Ordering::Relaxed);
+    }
+
+    /// How long to wait between the fetches of a fan-out, zero if they shouldn't be spaced out.
+    pub fn inter_request_delay(&self) -> Duration {
+        Duration::from_millis(self.inner.inter_request_delay_ms.load(Ordering::Relaxed))
+    }
+
+    /// Fetches the feed for `target`, which is a city name, a `geo:lat;lon` pair or a
+    /// station `@uid`.
+    pub async fn feed(&self, target: &str) -> Result<PollutionData, WaqiError> {
+        let target = clean_city(target);
+        let target = target.as_str();
+        if self.inner.demo {
+            return demo::feed(target).ok_or(WaqiError::Demo);
+        }
+        let key = target.to_lowercase();
+        if let Some(data) = self.inner.cache.get(&key) {
+            return Ok(data);
+        }
+        if self.inner.not_found.get(&key).is_some() {
+            return Err(WaqiError::CityNotFound);
+        }
+
+        let result = self.request(target, &["feed", target, ""], &[]).await;
+        if let Err(WaqiError::CityNotFound) = result {
+            self.inner
+                .not_found
+                .insert(key.clone(), NotFound::Unsearched);
+        }
+        let data: PollutionData = result?;
+        if !data.is_plausible() {
+            tracing::warn!(
+                city = target,
+                aqi = data.aqi,
+                "Discarding implausible WAQI reading"
+            );
+            return Err(WaqiError::Implausible(format!(
+                "AQI {} for {}",
+                data.aqi, data.city.name
+            )));
+        }
+        self.inner.cache.insert(key, data.clone());
+        Ok(data)
+    }
+
+    /// Fetches the raw feed `target` straight from WAQI like `feed_uncached`, noting how the
+    /// fetch went along the way.
+    pub async fn probe(&self, target: &str) -> Probe {
+        let target = target.trim();
+        let cached = self
+            .inner
+            .cache
+            .get(&clean_city(target).to_lowercase())
+            .is_some();
+        let mut response = None;
+        let started = Instant::now();
+        let result = self
+            .request_with_meta(target, &["feed", target, ""], &[], &mut response)
+            .await;
+        Probe {
+            cached,
+            response,
+            elapsed: started.elapsed(),
+            result,
+        }
+    }
+
+    /// Fetches the feed for `target` straight from WAQI, bypassing and not filling the caches.
+    pub async fn feed_uncached(&self, target: &str) -> Result<PollutionData, WaqiError> {
+        let target = clean_city(target);
+        if self.inner.demo {
+            return demo::feed(&target).ok_or(WaqiError::Demo);
+        }
+        self.request(&target, &["feed", &target, ""], &[]).await
+    }
+
+    /// Like `feed`, but when no station goes by `city` falls back to the best `search` match.
+    /// The matched station's name is returned alongside when that happened.
+    pub async fn feed_or_closest(
+        &self,
+        city: &str,
+    ) -> Result<(PollutionData, Option<String>), WaqiError> {
+        match self.feed(city).await {
+            Err(WaqiError::CityNotFound) => {
+                let key = clean_city(city).to_lowercase();
+                let closest = match self.inner.not_found.get(&key) {
+                    Some(NotFound::Closest(closest)) => closest,
+                    Some(NotFound::NoMatch) => return Err(WaqiError::CityNotFound),
+                    Some(NotFound::Unsearched) | None => {
+                        let closest = self.search(city).await?.into_iter().next();
+                        let entry = closest.clone().map_or(NotFound::NoMatch, NotFound::Closest);
+                        self.inner.not_found.insert(key, entry);
+                        closest.ok_or(WaqiError::CityNotFound)?
+                    }
+                };
+                let data = self.feed(&format!("@{}", closest.uid)).await?;
+                Ok((data, Some(closest.station.name)))
+            }
+            result => result.map(|data| (data, None)),
+        }
+    }
+
+    /// The best `search` match for `city` other than the station `feed` picks by default, for
+    /// names that resolve to a regional aggregate. Returns the station's name alongside.
+    pub async fn feed_alternative(&self, city: &str) -> Result<(PollutionData, String), WaqiError> {
+        let default = match self.feed(city).await {
+            Ok(data) => Some(i64::from(data.idx)),
+            Err(WaqiError::CityNotFound) => None,
+            Err(e) => return Err(e),
+        };
+        let results = self.search(city).await?;
+        // Without a default station the top match is what `/wis` shows, so skip that one.
+        let skip = default.or_else(|| results.first().map(|result| result.uid));
+        let alternative = results
+            .into_iter()
+            .find(|result| Some(result.uid) != skip)
+            .ok_or(WaqiError::CityNotFound)?;
+        let data = self.feed(&format!("@{}", alternative.uid)).await?;
+        Ok((data, alternative.station.name))
+    }
+
+    /// The reading of `target` on `date` from WAQI's historical endpoint. Tokens that may not
+    /// use it, stations without a history and days it doesn't cover are all
+    /// `HistoryUnavailable`. Past days don't change, but they're rare enough to skip the cache.
+    pub async fn history(&self, target: &str, date: NaiveDate) -> Result<HistoricalDay, WaqiError> {
+        let target = clean_city(target);
+        let date = date.format("%Y-%m-%d").to_string();
+        let days: Vec<HistoricalDay> = self
+            .request(
+                &target,
+                &["feed", &target, "history", ""],
+                &[("date", &date)],
+            )
+            .await
+            .map_err(|e| match e {
+                WaqiError::Api(_) | WaqiError::Parse(_) => WaqiError::HistoryUnavailable,
+                e => e,
+            })?;
+        days.into_iter()
+            .find(|day| day.day == date)
+            .ok_or(WaqiError::HistoryUnavailable)
+    }
+
+    /// Stations whose name matches `keyword`, best match first.
+    pub async fn search(&self, keyword: &str) -> Result<Vec<SearchResult>, WaqiError> {
+        let keyword = clean_city(keyword);
+        self.request(&keyword, &["search", ""], &[("keyword", &keyword)])
+            .await
+    }
+
+    /// Stations inside the box spanning `radius_km` around `lat`,`lon` in every direction.
+    pub async fn stations_around(
+        &self,
+        lat: f64,
+        lon: f64,
+        radius_km: f64,
+    ) -> Result<Vec<MapStation>, WaqiError> {
+        const KM_PER_DEGREE: f64 = 111.32;
+        let dlat = radius_km / KM_PER_DEGREE;
+        let dlon = radius_km / (KM_PER_DEGREE * lat.to_radians().cos().max(0.01));
+        let bounds = format!(
+            "{},{},{},{}",
+            lat - dlat,
+            lon - dlon,
+            lat + dlat,
+            lon + dlon
+        );
+        self.request(
+            &format!("map around {lat},{lon}"),
+            &["v2", "map", "bounds"],
+            &[("latlng", &bounds), ("networks", "all")],
+        )
+        .await
+    }
+
+    /// Sends a GET for the `path` segments (percent-encoded, so a city can't break out of its
+    /// segment) and unwraps WAQI's envelope. When the primary host can't be connected to, the
+    /// fallback host is tried once. `label` is only used for logging.
+    async fn request<T: DeserializeOwned>(
+        &self,
+        label: &str,
+        path: &[&str],
+        query: &[(&str, &str)],
+    ) -> Result<T, WaqiError> {
+        self.request_with_meta(label, path, query, &mut None).await
+    }
+
+    /// `request`, leaving the status, host and size of the last response in `meta`.
+    async fn request_with_meta<T: DeserializeOwned>(
+        &self,
+        label: &str,
+        path: &[&str],
+        query: &[(&str, &str)],
+        meta: &mut Option<ResponseMeta>,
+    ) -> Result<T, WaqiError> {
+        if self.inner.demo {
+            return Err(WaqiError::Demo);
+        }
+        let base = endpoint(&self.inner.base_url, path)?;
+        let fallback = match &self.inner.fallback_base_url {
+            Some(fallback) => Some(endpoint(fallback, path)?),
+            None => None,
+        };
+
+        // Each attempt uses the next healthy token, so an over-quota token costs one retry.
+        for _ in 0..self.inner.tokens.len() {
+            let Some((token_index, token)) = self.inner.tokens.next() else {
+                break;
+            };
+
+            if !self.inner.limiter.try_acquire(()) {
+                return Err(WaqiError::RateLimited);
+            }
+
+            let started = Instant::now();
+            let mut result = timeout(REQUEST_TIMEOUT, self.get(&base, token, query)).await;
+            // Only a host that can't be reached at all is worth another; HTTP errors would
+            // come back the same from the mirror.
+            if let (Ok(Err(e)), Some(fallback)) = (&result, &fallback) {
+                if e.is_connect() {
+                    tracing::warn!(
+                        city = label,
+                        "Couldn't connect to WAQI ({e}), trying {}",
+                        fallback.host_str().unwrap_or_default()
+                    );
+                    result = timeout(REQUEST_TIMEOUT, self.get(fallback, token, query)).await;
+                }
+            }
+
+            let response = match result {
+                Ok(Ok(response)) => response,
+                Ok(Err(e)) => return Err(WaqiError::Request(e)),
+                Err(_) => return Err(WaqiError::Timeout),
+            };
+            let status = response.status();
+            let host = response.url().host_str().unwrap_or_default().to_owned();
+            let json_type = response
+                .headers()
+                .get(reqwest::header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .is_some_and(|value| value.contains("json"));
+            let body = response.text().await.map_err(WaqiError::Request)?;
+            self.record_latency(label, started.elapsed());
+            *meta = Some(ResponseMeta {
+                status: status.as_u16(),
+                host,
+                body_bytes: body.len(),
+            });
+
+            // Errors WAQI reports itself are JSON even with an error status, so only a body
+            // that neither claims nor looks to be JSON is given up on.
+            if !json_type && !body.trim_start().starts_with('{') {
+                let snippet: String = body.chars().take(NON_JSON_SNIPPET_CHARS).collect();
+                tracing::warn!(
+                    city = label,
+                    status = status.as_u16(),
+                    "Non-JSON response from WAQI: {snippet:?}"
+                );
+                return Err(WaqiError::NonJson {
+                    status: status.as_u16(),
+                });
+            }
+
+            return match parse_envelope(&body).map_err(WaqiError::Parse)? {
+                Envelope::Ok(data) => Ok(*data),
+                Envelope::Error(message) if message.eq_ignore_ascii_case("over quota") => {
+                    self.inner.tokens.mark_over_quota(token_index);
+                    continue;
+                }
+                Envelope::Error(message) if message.eq_ignore_ascii_case("unknown station") => {
+                    Err(WaqiError::CityNotFound)
+                }
+                Envelope::Error(message) => Err(WaqiError::Api(message)),
+            };
+        }
+
+        Err(WaqiError::OverQuota)
+    }
+
+    fn get(
+        &self,
+        endpoint: &reqwest::Url,
+        token: &str,
+        query: &[(&str, &str)],
+    ) -> impl std::future::Future<Output = reqwest::Result<reqwest::Response>> {
+        let mut url = endpoint.clone();
+        url.query_pairs_mut().append_pair("token", token);
+        self.inner.client.get(url).query(query).send()
+    }
+
+    fn record_latency(&self, target: &str, elapsed: Duration) {
+        let threshold = self.inner.slow_fetch_threshold_ms.load(Ordering::Relaxed);
+        if elapsed >= Duration::from_millis(threshold) {
+            self.inner.slow_fetches.fetch_add(1, Ordering::Relaxed);
+            tracing::warn!(
+                city = target,
+                elapsed_ms = elapsed.as_millis() as u64,
+                "Slow WAQI fetch"
+            );
+        }
+    }
+
+    /// Requests left in the current minute of the global budget, along with the budget.
+    pub fn global_headroom(&self) -> (u32, u32) {
+        let limiter = &self.inner.limiter;
+        (limiter.remaining(&()), limiter.limit())
+    }
+
+    /// Writes the cached feeds to `path`, returning how many were saved.
+    pub fn save_cache(&self, path: &Path) -> Result<usize, String> {
+        let now = Utc::now().timestamp();
+        let feeds: Vec<PersistedFeed> = self
+            .inner
+            .cache
+            .entries()
+            .into_iter()
+            .map(|(key, age, data)| PersistedFeed {
+                key,
+                cached_at: now.saturating_sub(i64::try_from(age.as_secs()).unwrap_or(i64::MAX)),
+                data,
+            })
+            .collect();
+        let contents = serde_json::to_string(&feeds).map_err(|e| e.to_string())?;
+        std::fs::write(path, contents)
+            .map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+        Ok(feeds.len())
+    }
+
+    /// Loads the feeds saved by `save_cache` into the cache, dropping the ones that have expired
+    /// since. Returns how many were kept; a missing file keeps none.
+    pub fn load_cache(&self, path: &Path) -> Result<usize, String> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(format!("Failed to read {}: {e}", path.display())),
+        };
+        let feeds: Vec<PersistedFeed> = serde_json::from_str(&contents)
+            .map_err(|e| format!("Failed to parse {}: {e}", path.display()))?;
+
+        let now = Utc::now().timestamp();
+        let before = self.inner.cache.entries().len();
+        for feed in feeds {
+            // A clock that went backwards makes the feed look new rather than negative-aged.
+            let age = u64::try_from(now - feed.cached_at).unwrap_or(0);
+            self.inner
+                .cache
+                .insert_aged(feed.key, Duration::from_secs(age), feed.data);
+        }
+        Ok(self.inner.cache.entries().len() - before)
+    }
+
+    pub fn slow_fetches(&self) -> u64 {
+        self.inner.slow_fetches.load(Ordering::Relaxed)
+    }
+
+    pub fn token_health(&self) -> Vec<TokenHealth> {
+        self.inner.tokens.health()
+    }
+}
+
+/// `base` with the `path` segments appended.
+fn endpoint(base: &str, path: &[&str]) -> Result<reqwest::Url, WaqiError> {
+    let mut url =
+        reqwest::Url::parse(base).map_err(|e| WaqiError::Api(format!("Invalid base URL: {e}")))?;
+    url.path_segments_mut()
+        .map_err(|_| WaqiError::Api("Invalid base URL".into()))?
+        .pop_if_empty()
+        .extend(path);
+    Ok(url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    /// A WAQI feed response: PM2.5 dominant at 87.
+    const FEED: &str = include_str!("../fuzz/corpus/parse_response/feed.json");
+
+    /// A client talking to a local server that answers every request with `status`,
+    /// `content_type` and `body`, along with the number of requests the server got.
+    async fn mock_waqi(status: u16, content_type: &str, body: String) -> (Waqi, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = Config {
+            aqi_token: "test".into(),
+            base_url: format!("http://{}", listener.local_addr().unwrap()),
+            ..Config::default()
+        };
+        let response = format!(
+            "HTTP/1.1 {status} Mock\r\nContent-Type: {content_type}\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                counter.fetch_add(1, Ordering::Relaxed);
+                let mut request = [0; 4096];
+                let _ = socket.read(&mut request).await;
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+        (Waqi::new(&config), requests)
+    }
+
+    #[tokio::test]
+    async fn out_of_range_aqi_is_implausible() {
+        let body = FEED.replace(r#""aqi":87"#, r#""aqi":5000"#);
+        let (waqi, _) = mock_waqi(200, "application/json", body).await;
+        assert!(matches!(
+            waqi.feed("tehran").await,
+            Err(WaqiError::Implausible(_))
+        ));
+
+        let (waqi, _) = mock_waqi(200, "application/json", FEED.to_owned()).await;
+        assert_eq!(waqi.feed("tehran").await.unwrap().aqi, 87);
+    }
+
+    #[tokio::test]
+    async fn known_bad_city_fails_without_another_request() {
+        let body = r#"{"status":"error","data":"Unknown station"}"#.to_owned();
+        let (waqi, requests) = mock_waqi(200, "application/json", body).await;
+        for _ in 0..2 {
+            assert!(matches!(
+                waqi.feed("nowhere").await,
+                Err(WaqiError::CityNotFound)
+            ));
+        }
+        assert_eq!(requests.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn city_stays_inside_its_path_segment() {
+        let url = endpoint("https://api.waqi.info/", &["feed", "Tehran 🌆/../x?y", ""]).unwrap();
+        assert_eq!(
+            url.as_str(),
+            "https://api.waqi.info/feed/Tehran%20%F0%9F%8C%86%2F..%2Fx%3Fy/"
+        );
+    }
+
+    #[tokio::test]
+    async fn html_error_page_is_non_json() {
+        let body = "<html><body><h1>503 Service Unavailable</h1></body></html>".to_owned();
+        let (waqi, _) = mock_waqi(503, "text/html", body).await;
+        let error = waqi.feed("tehran").await.unwrap_err();
+        assert!(
+            matches!(error, WaqiError::NonJson { status: 503 }),
+            "{error}"
+        );
+        assert_eq!(
+            error.to_string(),
+            "Upstream returned a non-JSON response (status 503)"
+        );
+    }
+}